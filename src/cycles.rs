@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Shape of the sequence start, step(start), step(step(start)), ...
+/// The first `mu` states happen once; after that the sequence repeats every `lambda` states.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Cycle {
+    pub mu: usize,
+    pub lambda: usize,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Method {
+    /// Tortoise and hare; constant memory, but steps the sequence about three times over
+    Floyd,
+    /// Teleporting tortoise; constant memory and usually fewer steps than Floyd
+    Brent,
+    /// Remembers every state it sees; one pass, but the memory grows with mu + lambda
+    Hashing,
+}
+
+impl std::str::FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Method, String> {
+        match s {
+            "floyd" => Ok(Method::Floyd),
+            "brent" => Ok(Method::Brent),
+            "hashing" => Ok(Method::Hashing),
+            other => Err(format!("Unrecognized cycle detection method {}", other)),
+        }
+    }
+}
+
+fn floyd<T: Eq + Clone, F: FnMut(&T) -> T>(start: &T, mut step: F) -> Cycle {
+    let mut tortoise = step(start);
+    let mut hare = step(&tortoise);
+
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        let halfway = step(&hare);
+        hare = step(&halfway);
+    }
+
+    // the distance between them is now a multiple of lambda, so walking one from the
+    // start and the other from here at the same speed, they meet at the cycle's start
+    let mut mu = 0;
+    tortoise = start.clone();
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        mu += 1;
+    }
+
+    let mut lambda = 1;
+    hare = step(&tortoise);
+    while tortoise != hare {
+        hare = step(&hare);
+        lambda += 1;
+    }
+
+    Cycle { mu, lambda }
+}
+
+fn brent<T: Eq + Clone, F: FnMut(&T) -> T>(start: &T, mut step: F) -> Cycle {
+    let mut power = 1;
+    let mut lambda = 1;
+    let mut tortoise = start.clone();
+    let mut hare = step(start);
+
+    while tortoise != hare {
+        if power == lambda {
+            tortoise = hare.clone();
+            power *= 2;
+            lambda = 0;
+        }
+        hare = step(&hare);
+        lambda += 1;
+    }
+
+    // start the hare lambda steps ahead, then walk both until they line up
+    let mut mu = 0;
+    tortoise = start.clone();
+    hare = start.clone();
+    for _ in 0..lambda {
+        hare = step(&hare);
+    }
+
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        mu += 1;
+    }
+
+    Cycle { mu, lambda }
+}
+
+fn hashing<T: Eq + Hash + Clone, F: FnMut(&T) -> T>(start: &T, mut step: F) -> Cycle {
+    let mut seen: HashMap<T, usize> = HashMap::new();
+    let mut state = start.clone();
+
+    loop {
+        let index = seen.len();
+        if let Some(first) = seen.insert(state.clone(), index) {
+            return Cycle {
+                mu: first,
+                lambda: index - first,
+            };
+        }
+        state = step(&state);
+    }
+}
+
+/// Finds where the sequence generated by `step` starts repeating. Never returns if it doesn't.
+pub fn find_cycle<T, F>(method: Method, start: &T, step: F) -> Cycle
+where
+    T: Eq + Hash + Clone,
+    F: FnMut(&T) -> T,
+{
+    match method {
+        Method::Floyd => floyd(start, step),
+        Method::Brent => brent(start, step),
+        Method::Hashing => hashing(start, step),
+    }
+}
+
+/// The state after applying `step` n times, skipping all the full laps of the cycle
+pub fn nth_state<T, F>(method: Method, start: &T, mut step: F, n: u64) -> T
+where
+    T: Eq + Hash + Clone,
+    F: FnMut(&T) -> T,
+{
+    let cycle = find_cycle(method, start, &mut step);
+
+    let (mu, lambda) = (cycle.mu as u64, cycle.lambda as u64);
+    let remaining = if n < mu { n } else { mu + (n - mu) % lambda };
+
+    let mut state = start.clone();
+    for _ in 0..remaining {
+        state = step(&state);
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METHODS: [Method; 3] = [Method::Floyd, Method::Brent, Method::Hashing];
+
+    #[test]
+    fn all_methods_agree() {
+        // rho-shaped sequences with a variety of tails and loop lengths
+        for modulus in 2..60_u64 {
+            for start in 0..modulus {
+                let step = |x: &u64| (x * x + 1) % modulus;
+                let expected = find_cycle(Method::Hashing, &start, step);
+
+                for method in METHODS.iter().copied() {
+                    assert_eq!(
+                        find_cycle(method, &start, step),
+                        expected,
+                        "{:?} on modulus {} start {}",
+                        method,
+                        modulus,
+                        start
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn known_shapes() {
+        // 0, 1, 2, 3, 4, 5, 3, 4, 5, ...
+        let step = |x: &u32| if *x == 5 { 3 } else { x + 1 };
+
+        for method in METHODS.iter().copied() {
+            assert_eq!(find_cycle(method, &0, step), Cycle { mu: 3, lambda: 3 });
+            assert_eq!(find_cycle(method, &4, step), Cycle { mu: 0, lambda: 3 });
+            assert_eq!(
+                find_cycle(method, &7, |x: &u32| *x),
+                Cycle { mu: 0, lambda: 1 }
+            );
+
+            assert_eq!(nth_state(method, &0, step, 2), 2);
+            assert_eq!(nth_state(method, &0, step, 6), 3);
+            assert_eq!(nth_state(method, &0, step, 1_000_000_000_000), 4);
+        }
+    }
+}
@@ -1,39 +1,202 @@
+use std::io::{Read, Seek, SeekFrom};
+
 const INPUT: &str = include_str!("input/1.txt");
 
-fn run_1a_with_input(input_str: &str) -> u64 {
-    let digits: Vec<u32> = input_str.chars().map(|c| c.to_digit(10).unwrap()).collect();
+#[derive(Debug)]
+enum CaptchaError {
+    /// Byte offset into the input, and the byte that isn't a digit in the radix
+    InvalidDigit {
+        position: u64,
+        found: u8,
+    },
+    Io(std::io::Error),
+}
 
-    let mut total: u64 = 0;
-    for i in 1..digits.len() {
-        if digits[i] == digits[i - 1] {
-            total += digits[i] as u64;
+impl std::fmt::Display for CaptchaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptchaError::InvalidDigit { position, found } => {
+                write!(f, "Invalid digit {:?} at byte {}", *found as char, position)
+            }
+            CaptchaError::Io(e) => write!(f, "Could not read input: {}", e),
         }
     }
+}
+
+impl From<std::io::Error> for CaptchaError {
+    fn from(e: std::io::Error) -> CaptchaError {
+        CaptchaError::Io(e)
+    }
+}
+
+/// Feeds every digit of the stream to `on_digit`, in order, and returns how many there were.
+/// Whitespace is skipped, so trailing newlines are fine.
+fn for_each_digit<R: Read, F: FnMut(u8)>(
+    reader: &mut R,
+    radix: u32,
+    mut on_digit: F,
+) -> Result<u64, CaptchaError> {
+    let mut buffer = [0_u8; 1 << 16];
+    let mut position: u64 = 0;
+    let mut count: u64 = 0;
 
-    if digits[digits.len() - 1] == digits[0] {
-        total += digits[0] as u64;
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(count),
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        for byte in buffer[..read].iter().copied() {
+            if !byte.is_ascii_whitespace() {
+                match (byte as char).to_digit(radix) {
+                    Some(digit) => {
+                        on_digit(digit as u8);
+                        count += 1;
+                    }
+                    None => {
+                        return Err(CaptchaError::InvalidDigit {
+                            position,
+                            found: byte,
+                        })
+                    }
+                }
+            }
+            position += 1;
+        }
     }
+}
 
-    total
+/// One place in a seekable stream of digits, with its own buffer, so two of them can take
+/// turns on the same reader; each seeks back to where it left off before refilling
+struct DigitCursor {
+    buffer: Vec<u8>,
+    /// Where the stream starts, so errors give the same positions `for_each_digit` does
+    start: u64,
+    /// Where the next refill reads from
+    pos: u64,
+    filled: usize,
+    next: usize,
 }
 
-fn run_1b_with_input(input_str: &str) -> u64 {
-    let digits: Vec<u32> = input_str.chars().map(|c| c.to_digit(10).unwrap()).collect();
+impl DigitCursor {
+    fn new(start: u64) -> DigitCursor {
+        DigitCursor {
+            buffer: vec![0; 1 << 16],
+            start,
+            pos: start,
+            filled: 0,
+            next: 0,
+        }
+    }
+
+    /// Back to the start of the stream, keeping the buffer
+    fn rewind(&mut self) {
+        self.pos = self.start;
+        self.filled = 0;
+        self.next = 0;
+    }
+
+    /// The next digit, skipping whitespace. The stream has already been counted, so running
+    /// out means it got shorter in between.
+    fn next_digit<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        radix: u32,
+    ) -> Result<u8, CaptchaError> {
+        loop {
+            if self.next == self.filled {
+                reader.seek(SeekFrom::Start(self.pos))?;
+                self.filled = loop {
+                    match reader.read(&mut self.buffer) {
+                        Ok(read) => break read,
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(e.into()),
+                    }
+                };
+                if self.filled == 0 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "input got shorter while it was being read",
+                    )
+                    .into());
+                }
+                self.pos += self.filled as u64;
+                self.next = 0;
+            }
 
-    if digits.len() % 2 != 0 {
-        panic!("Cannot handle a list of length {}", digits.len());
+            let byte = self.buffer[self.next];
+            let position = self.pos - self.start - (self.filled - self.next) as u64;
+            self.next += 1;
+
+            if !byte.is_ascii_whitespace() {
+                return match (byte as char).to_digit(radix) {
+                    Some(digit) => Ok(digit as u8),
+                    None => Err(CaptchaError::InvalidDigit {
+                        position,
+                        found: byte,
+                    }),
+                };
+            }
+        }
     }
+}
 
-    let half_length = digits.len() / 2;
+/// Sums every digit which matches the digit `offset(len)` places further along the
+/// circular list. Both parts of the puzzle are special cases: an offset of 1 for part A
+/// and half the length for part B.
+///
+/// The stream is read once to count (and check) the digits so the offset can be worked
+/// out, then compared against itself through two cursors k digits apart, where k is the
+/// offset reduced modulo the length; the one in front goes back to the start when it runs
+/// off the end. Each cursor only buffers a fixed-size block, so memory stays the same
+/// however long the input and whatever the offset.
+fn captcha_stream<R: Read + Seek, F: Fn(u64) -> u64>(
+    mut reader: R,
+    radix: u32,
+    offset: F,
+) -> Result<u64, CaptchaError> {
+    let start = reader.stream_position()?;
+    let len = for_each_digit(&mut reader, radix, |_| {})?;
+
+    if len == 0 {
+        return Ok(0);
+    }
+
+    let k = offset(len) % len;
+
+    let mut here = DigitCursor::new(start);
+    let mut ahead = DigitCursor::new(start);
+    for _ in 0..k {
+        ahead.next_digit(&mut reader, radix)?;
+    }
 
     let mut total: u64 = 0;
-    for i in 0..half_length {
-        if digits[i] == digits[i + half_length] {
-            total += (digits[i] as u64) * 2;
+    for i in 0..len {
+        if i == len - k {
+            ahead.rewind();
+        }
+
+        let digit = here.next_digit(&mut reader, radix)?;
+        if ahead.next_digit(&mut reader, radix)? == digit {
+            total += digit as u64;
         }
     }
 
-    total
+    Ok(total)
+}
+
+fn captcha<F: Fn(u64) -> u64>(input: &str, radix: u32, offset: F) -> Result<u64, CaptchaError> {
+    captcha_stream(std::io::Cursor::new(input.as_bytes()), radix, offset)
+}
+
+fn run_1a_with_input(input_str: &str) -> u64 {
+    captcha(input_str, 10, |_| 1).unwrap()
+}
+
+fn run_1b_with_input(input_str: &str) -> u64 {
+    captcha(input_str, 10, |len| len / 2).unwrap()
 }
 
 pub fn run_1a() -> u64 {
@@ -44,6 +207,32 @@ pub fn run_1b() -> u64 {
     run_1b_with_input(INPUT)
 }
 
+/// Streams a captcha file from disk; the offset is "next", "half" or a fixed number of places
+pub fn captcha_file_1(path: &str, offset: &str, radix: u32) -> String {
+    if !(2..=36).contains(&radix) {
+        return format!("Radix must be between 2 and 36, not {}", radix);
+    }
+
+    let file = match std::fs::File::open(path) {
+        Ok(file) => std::io::BufReader::new(file),
+        Err(e) => return format!("Could not open '{}': {}", path, e),
+    };
+
+    let result = match offset {
+        "next" => captcha_stream(file, radix, |_| 1),
+        "half" => captcha_stream(file, radix, |len| len / 2),
+        other => match other.parse::<u64>() {
+            Ok(k) => captcha_stream(file, radix, |_| k),
+            Err(_) => return format!("Unrecognized offset {}", other),
+        },
+    };
+
+    match result {
+        Ok(total) => total.to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -62,4 +251,105 @@ mod tests {
     fn sample_1b_2() {
         assert_eq!(run_1b_with_input("1212"), 6)
     }
+
+    /// Straightforward in-memory version to check the streaming one against
+    fn captcha_naive(digits: &[u8], k: usize) -> u64 {
+        let n = digits.len();
+        (0..n)
+            .filter(|&i| digits[i] == digits[(i + k) % n])
+            .map(|i| digits[i] as u64)
+            .sum()
+    }
+
+    #[test]
+    fn captcha_offsets() {
+        assert_eq!(run_1a_with_input("1111\n"), 4);
+        assert_eq!(run_1a_with_input("91212129"), 9);
+        assert_eq!(run_1b_with_input("123425"), 4);
+        assert_eq!(run_1b_with_input("12131415"), 4);
+        assert_eq!(run_1b_with_input(""), 0);
+
+        let input: String = (0..200_u64)
+            .map(|i| std::char::from_digit(((i * i + 7 * i) % 5) as u32, 10).unwrap())
+            .collect();
+        let digits: Vec<u8> = input.bytes().map(|b| b - b'0').collect();
+
+        for k in [0, 1, 3, 99, 100, 101, 199, 200, 1000].iter().copied() {
+            assert_eq!(
+                captcha(&input, 10, |_| k).unwrap(),
+                captcha_naive(&digits, k as usize % digits.len()),
+                "Offset {}",
+                k
+            );
+        }
+    }
+
+    /// Keeps track of how much is asked for at once and how much gets read overall
+    struct CountingReader<R> {
+        inner: R,
+        largest_read: usize,
+        total_read: u64,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.largest_read = self.largest_read.max(buf.len());
+            let read = self.inner.read(buf)?;
+            self.total_read += read as u64;
+            Ok(read)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn captcha_memory() {
+        // odd, so "half" doesn't split it evenly, and long enough that holding half of it
+        // would stand out
+        let len = 1_000_001_u64;
+        let input: Vec<u8> = (0..len)
+            .map(|i| b'0' + ((i * i + 3 * i) % 7) as u8)
+            .chain(b"\n".iter().copied())
+            .collect();
+        let digits: Vec<u8> = input[..len as usize].iter().map(|b| b - b'0').collect();
+
+        for k in [len / 2, len - 3].iter().copied() {
+            let mut reader = CountingReader {
+                inner: std::io::Cursor::new(&input),
+                largest_read: 0,
+                total_read: 0,
+            };
+
+            assert_eq!(
+                captcha_stream(&mut reader, 10, |_| k).unwrap(),
+                captcha_naive(&digits, k as usize),
+                "Offset {}",
+                k
+            );
+
+            // a fixed-size block at a time, and no more than one pass to count, one to move
+            // the cursor in front into place, and one for each cursor
+            assert!(reader.largest_read <= 1 << 16);
+            assert!(reader.total_read <= 4 * (len + 1) + 2 * (1 << 16));
+        }
+    }
+
+    #[test]
+    fn captcha_radix_and_errors() {
+        assert_eq!(captcha("ffa0af", 16, |_| 1).unwrap(), 15 + 15);
+        assert_eq!(captcha("1011", 2, |len| len / 2).unwrap(), 2);
+
+        match captcha("12\n3x4", 10, |_| 1) {
+            Err(CaptchaError::InvalidDigit { position, found }) => {
+                assert_eq!((position, found), (4, b'x'));
+            }
+            other => panic!("Expected an invalid digit, got {:?}", other),
+        }
+
+        assert!(captcha("1239", 8, |_| 1).is_err());
+    }
 }
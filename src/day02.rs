@@ -1,64 +1,341 @@
 const INPUT: &str = include_str!("input/2.txt");
 
-fn run_2a_with_input(input: &str) -> u64 {
-    let mut total = 0;
-
-    for line in input.lines() {
-        let mut running_min = u64::max_value();
-        let mut running_max = u64::min_value();
-
-        for token in line
-            .split_whitespace()
-            .map(|token| token.parse::<u64>().unwrap())
-        {
-            running_min = running_min.min(token);
-            running_max = running_max.max(token);
+/// Splitting exported spreadsheets into cells
+mod table {
+    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+    pub(super) enum Format {
+        /// Cells separated by any amount of whitespace, like the puzzle input; no quoting
+        Whitespace,
+        /// Cells separated by the given character, with RFC 4180 style quoting: a quoted
+        /// cell may contain the delimiter, newlines, and doubled quotes for literal quotes
+        Delimited(char),
+    }
+
+    #[derive(Clone, Eq, PartialEq, Debug)]
+    pub(super) struct Sheet {
+        pub(super) header: Option<Vec<String>>,
+        pub(super) rows: Vec<Vec<String>>,
+    }
+
+    fn split_delimited(input: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        let mut cell = String::new();
+        let mut in_quotes = false;
+        let mut line = 1;
+
+        let mut chars = input.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if in_quotes {
+                match c {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        cell.push('"');
+                    }
+                    '"' => in_quotes = false,
+                    '\n' => {
+                        line += 1;
+                        cell.push(c);
+                    }
+                    _ => cell.push(c),
+                }
+                continue;
+            }
+
+            match c {
+                '"' if cell.is_empty() => in_quotes = true,
+                '"' => return Err(format!("Line {}: stray quote in '{}'", line, cell)),
+                '\r' if chars.peek() == Some(&'\n') => {}
+                '\n' => {
+                    row.push(std::mem::take(&mut cell));
+                    rows.push(std::mem::take(&mut row));
+                    line += 1;
+                }
+                c if c == delimiter => row.push(std::mem::take(&mut cell)),
+                _ => cell.push(c),
+            }
         }
 
-        total += running_max - running_min;
+        if in_quotes {
+            return Err(format!("Line {}: unterminated quote", line));
+        }
+
+        if !cell.is_empty() || !row.is_empty() {
+            row.push(cell);
+            rows.push(row);
+        }
+
+        Ok(rows)
     }
 
-    total
-}
+    pub(super) fn parse(input: &str, format: Format, has_header: bool) -> Result<Sheet, String> {
+        let mut rows = match format {
+            Format::Whitespace => input
+                .lines()
+                .map(|line| line.split_whitespace().map(|t| t.to_string()).collect())
+                .collect(),
+            Format::Delimited(delimiter) => split_delimited(input, delimiter)?,
+        };
 
-fn run_2b_with_input(input: &str) -> u64 {
-    let mut total = 0;
+        let header = if has_header && !rows.is_empty() {
+            Some(rows.remove(0))
+        } else {
+            None
+        };
 
-    'main: for line in input.lines() {
-        let nums: Vec<u64> = line
-            .split_whitespace()
-            .map(|t| t.parse().unwrap())
-            .collect();
+        Ok(Sheet { header, rows })
+    }
 
-        for i in 1..nums.len() {
-            for j in 0..i {
-                let a = nums[i];
-                let b = nums[j];
+    #[cfg(test)]
+    mod tests {
+        use super::*;
 
-                if a % b == 0 {
-                    total += a / b;
-                    continue 'main;
-                } else if b % a == 0 {
-                    total += b / a;
-                    continue 'main;
+        #[test]
+        fn test_parse_csv() {
+            let input = "name,\"a, b\",c\r\nfirst,1,\"2\"\n\"second\nrow\",\"say \"\"hi\"\"\",\n";
+            let sheet = parse(input, Format::Delimited(','), true).unwrap();
+
+            assert_eq!(
+                sheet,
+                Sheet {
+                    header: Some(vec!["name".into(), "a, b".into(), "c".into()]),
+                    rows: vec![
+                        vec!["first".into(), "1".into(), "2".into()],
+                        vec!["second\nrow".into(), "say \"hi\"".into(), "".into()],
+                    ],
                 }
+            );
+
+            assert!(parse("a,\"b", Format::Delimited(','), false).is_err());
+            assert!(parse("a,b\"c\"", Format::Delimited(','), false).is_err());
+        }
+    }
+}
+
+use table::Format;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum RowError {
+    Empty,
+    NotANumber { column: usize, text: String },
+    NoEvenDivisor,
+    Overflow,
+}
+
+impl std::fmt::Display for RowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RowError::Empty => write!(f, "no numbers in row"),
+            RowError::NotANumber { column, text } => {
+                write!(f, "column {} is not a number: '{}'", column + 1, text)
             }
+            RowError::NoEvenDivisor => write!(f, "no pair of numbers divides evenly"),
+            RowError::Overflow => write!(f, "result overflowed"),
         }
+    }
+}
 
-        panic!("Divisor not found in line {:?}", nums);
+/// How a row of numbers gets boiled down to its contribution to the checksum
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Reducer {
+    /// Largest minus smallest (part A)
+    Range,
+    /// The quotient of the one pair where one number evenly divides the other (part B)
+    EvenQuotient,
+    Sum,
+    Product,
+    Gcd,
+    Max,
+    Min,
+}
+
+impl std::str::FromStr for Reducer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Reducer, String> {
+        match s {
+            "range" => Ok(Reducer::Range),
+            "even-quotient" => Ok(Reducer::EvenQuotient),
+            "sum" => Ok(Reducer::Sum),
+            "product" => Ok(Reducer::Product),
+            "gcd" => Ok(Reducer::Gcd),
+            "max" => Ok(Reducer::Max),
+            "min" => Ok(Reducer::Min),
+            other => Err(format!("Unrecognized row reducer {}", other)),
+        }
     }
+}
 
-    total
+/// Works on magnitudes, since |i64::MIN| doesn't fit in an i64
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
 }
 
-pub fn run_2a() -> u64 {
+impl Reducer {
+    fn reduce(self, nums: &[i64]) -> Result<i64, RowError> {
+        if nums.is_empty() {
+            return Err(RowError::Empty);
+        }
+
+        let max = nums.iter().copied().max().unwrap();
+        let min = nums.iter().copied().min().unwrap();
+
+        match self {
+            Reducer::Range => max.checked_sub(min).ok_or(RowError::Overflow),
+            Reducer::Max => Ok(max),
+            Reducer::Min => Ok(min),
+            Reducer::Sum => nums
+                .iter()
+                .try_fold(0_i64, |acc, &n| acc.checked_add(n))
+                .ok_or(RowError::Overflow),
+            Reducer::Product => nums
+                .iter()
+                .try_fold(1_i64, |acc, &n| acc.checked_mul(n))
+                .ok_or(RowError::Overflow),
+            Reducer::Gcd => {
+                let g = nums.iter().fold(0, |g, &n| gcd(g, n.unsigned_abs()));
+                if g > i64::MAX as u64 {
+                    Err(RowError::Overflow)
+                } else {
+                    Ok(g as i64)
+                }
+            }
+            Reducer::EvenQuotient => {
+                for i in 1..nums.len() {
+                    for j in 0..i {
+                        let a = nums[i];
+                        let b = nums[j];
+
+                        // zero "divides evenly" by everything, which isn't useful
+                        if a == 0 || b == 0 {
+                            continue;
+                        }
+
+                        // wrapping, since i64::MIN % -1 overflows even though it divides
+                        // evenly; the division then reports the overflow
+                        if a.wrapping_rem(b) == 0 {
+                            return a.checked_div(b).ok_or(RowError::Overflow);
+                        } else if b.wrapping_rem(a) == 0 {
+                            return b.checked_div(a).ok_or(RowError::Overflow);
+                        }
+                    }
+                }
+
+                Err(RowError::NoEvenDivisor)
+            }
+        }
+    }
+}
+
+/// Numbers in a row; blank cells are skipped, since exported spreadsheets are full of them
+fn row_numbers(row: &[String]) -> Result<Vec<i64>, RowError> {
+    row.iter()
+        .enumerate()
+        .filter(|(_, cell)| !cell.trim().is_empty())
+        .map(|(column, cell)| {
+            cell.trim().parse().map_err(|_| RowError::NotANumber {
+                column,
+                text: cell.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Each row's contribution to the checksum, or why it couldn't make one
+fn contributions(rows: &[Vec<String>], reducer: Reducer) -> Vec<Result<i64, RowError>> {
+    rows.iter()
+        .map(|row| row_numbers(row).and_then(|nums| reducer.reduce(&nums)))
+        .collect()
+}
+
+fn checksum(rows: &[Vec<String>], reducer: Reducer) -> Result<i64, String> {
+    contributions(rows, reducer)
+        .into_iter()
+        .enumerate()
+        .try_fold(0_i64, |total, (row_num, contribution)| {
+            let contribution = contribution.map_err(|e| format!("Row {}: {}", row_num + 1, e))?;
+            total
+                .checked_add(contribution)
+                .ok_or_else(|| "Checksum overflowed".to_string())
+        })
+}
+
+fn run_2a_with_input(input: &str) -> i64 {
+    let sheet = table::parse(input, Format::Whitespace, false).unwrap();
+    checksum(&sheet.rows, Reducer::Range).unwrap()
+}
+
+fn run_2b_with_input(input: &str) -> i64 {
+    let sheet = table::parse(input, Format::Whitespace, false).unwrap();
+    checksum(&sheet.rows, Reducer::EvenQuotient).unwrap()
+}
+
+pub fn run_2a() -> i64 {
     run_2a_with_input(INPUT)
 }
 
-pub fn run_2b() -> u64 {
+pub fn run_2b() -> i64 {
     run_2b_with_input(INPUT)
 }
 
+/// Per-row checksum report for an exported spreadsheet. The format is "csv", "tsv" or
+/// "whitespace"; rows which can't be reduced are reported and left out of the total.
+pub fn report_2(input: &str, reducer: &str, format: &str, has_header: bool) -> String {
+    let reducer: Reducer = match reducer.parse() {
+        Ok(reducer) => reducer,
+        Err(e) => return e,
+    };
+
+    let format = match format {
+        "csv" => Format::Delimited(','),
+        "tsv" => Format::Delimited('\t'),
+        "whitespace" => Format::Whitespace,
+        other => return format!("Unrecognized spreadsheet format {}", other),
+    };
+
+    let sheet = match table::parse(input, format, has_header) {
+        Ok(sheet) => sheet,
+        Err(e) => return e,
+    };
+
+    let mut out = String::new();
+    if let Some(header) = sheet.header {
+        out.push_str(&format!("Columns: {}\n", header.join(", ")));
+    }
+
+    // None once it's overflowed, which `checksum` would have refused too
+    let mut total: Option<i64> = Some(0);
+    let mut failed = 0;
+
+    for (row_num, contribution) in contributions(&sheet.rows, reducer).into_iter().enumerate() {
+        match contribution {
+            Ok(val) => {
+                total = total.and_then(|total| total.checked_add(val));
+                out.push_str(&format!("Row {}: {}\n", row_num + 1, val));
+            }
+            Err(e) => {
+                failed += 1;
+                out.push_str(&format!("Row {}: skipped, {}\n", row_num + 1, e));
+            }
+        }
+    }
+
+    match total {
+        Some(total) => out.push_str(&format!("Checksum: {}", total)),
+        None => out.push_str("Checksum overflowed"),
+    }
+    if failed > 0 {
+        out.push_str(&format!(" ({} rows skipped)", failed));
+    }
+
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -74,4 +351,77 @@ mod tests {
         let input = "5 9 2 8 \n 9 4 7 3 \n 3 8 6 5";
         assert_eq!(run_2b_with_input(input), 9);
     }
+
+    #[test]
+    fn reducers() {
+        let row = [12, -18, 30];
+
+        assert_eq!(Reducer::Range.reduce(&row), Ok(48));
+        assert_eq!(Reducer::Sum.reduce(&row), Ok(24));
+        assert_eq!(Reducer::Product.reduce(&row), Ok(-6480));
+        assert_eq!(Reducer::Gcd.reduce(&row), Ok(6));
+        assert_eq!(Reducer::Max.reduce(&row), Ok(30));
+        assert_eq!(Reducer::Min.reduce(&row), Ok(-18));
+        assert_eq!(
+            Reducer::EvenQuotient.reduce(&row),
+            Err(RowError::NoEvenDivisor)
+        );
+        assert_eq!(Reducer::EvenQuotient.reduce(&[0, 7, 21]), Ok(3));
+        assert_eq!(Reducer::Sum.reduce(&[]), Err(RowError::Empty));
+        assert_eq!(
+            Reducer::Product.reduce(&[i64::MAX, 2]),
+            Err(RowError::Overflow)
+        );
+
+        // i64::MIN has no positive counterpart, so these overflow rather than panic
+        assert_eq!(
+            Reducer::EvenQuotient.reduce(&[i64::MIN, -1]),
+            Err(RowError::Overflow)
+        );
+        assert_eq!(
+            Reducer::EvenQuotient.reduce(&[-1, i64::MIN]),
+            Err(RowError::Overflow)
+        );
+        assert_eq!(
+            Reducer::EvenQuotient.reduce(&[i64::MIN, 2]),
+            Ok(i64::MIN / 2)
+        );
+        assert_eq!(Reducer::Gcd.reduce(&[i64::MIN]), Err(RowError::Overflow));
+        assert_eq!(Reducer::Gcd.reduce(&[i64::MIN, 6]), Ok(2));
+
+        // every row fits, but their total doesn't, and the report says so like checksum does
+        let input = "0 9223372036854775807\n-1 0\n0 9223372036854775807\n";
+        assert_eq!(
+            report_2(input, "range", "whitespace", false),
+            "Row 1: 9223372036854775807
+Row 2: 1
+Row 3: 9223372036854775807
+Checksum overflowed"
+        );
+        let sheet = table::parse(input, Format::Whitespace, false).unwrap();
+        assert_eq!(
+            checksum(&sheet.rows, Reducer::Range),
+            Err("Checksum overflowed".to_string())
+        );
+    }
+
+    #[test]
+    fn spreadsheet_report() {
+        let input = "a,b,c\n5,9,2\n9,,\"3\"\n3,x,5\n";
+
+        assert_eq!(
+            report_2(input, "even-quotient", "csv", true),
+            "Columns: a, b, c
+Row 1: skipped, no pair of numbers divides evenly
+Row 2: 3
+Row 3: skipped, column 2 is not a number: 'x'
+Checksum: 3 (2 rows skipped)"
+        );
+
+        let sheet = table::parse(input, Format::Delimited(','), true).unwrap();
+        assert_eq!(
+            checksum(&sheet.rows, Reducer::EvenQuotient),
+            Err("Row 1: no pair of numbers divides evenly".to_string())
+        );
+    }
 }
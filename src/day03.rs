@@ -1,175 +1,306 @@
-use std::collections::HashMap;
-
 const INPUT: &str = "312051";
 
-fn get_ring(input: u64) -> u64 {
-    if input <= 1 {
-        return 0;
-    }
-
-    let mut ring_num = 0;
+/// Floor of the square root; the float estimate is only off by one near the top of the u64 range
+fn isqrt(n: u64) -> u64 {
+    let n = n as u128;
+    let mut root = (n as f64).sqrt() as u128;
 
-    loop {
-        ring_num += 1;
-
-        let n = ring_num * 2 + 1;
-        if n * n >= input {
-            return ring_num;
-        }
+    while root * root > n {
+        root -= 1;
+    }
+    while (root + 1) * (root + 1) <= n {
+        root += 1;
     }
+
+    root as u64
 }
 
-/// Technically O(n) where n is input; this could be improved with More Math
-/// but who cares, input is like 6 digits, it takes a millisecond
-fn run_3a_with_input(input: u64) -> u64 {
+/// Ring k holds the numbers from (2k-1)^2 + 1 up to (2k+1)^2
+fn get_ring(input: u64) -> u64 {
     if input <= 1 {
         return 0;
     }
 
-    let ring = get_ring(input);
-
-    // starts on right edge, at the next-to-the-bottom square
-    let dist = |x: i64, y: i64| (x.abs() + y.abs()) as u64;
-
-    let iring = ring as i64;
-    let ring_adj = ring * 2 - 1;
+    // round up: an odd root means the input is past that square's ring
+    let root = isqrt(input - 1);
+    root / 2 + root % 2
+}
 
-    let start = ring_adj * ring_adj + 1;
+/// Position of the given square in the spiral, with 1 at the origin and y pointing up.
+/// Each ring starts just above its bottom-right corner, then goes up the right edge,
+/// left along the top, down the left side and right along the bottom.
+fn spiral_coords(input: u64) -> (i64, i64) {
+    assert!(input >= 1, "The spiral starts at 1");
 
-    let mut n = start;
+    if input == 1 {
+        return (0, 0);
+    }
 
-    let mut x: i64 = iring;
-    let mut y: i64 = -iring + 1;
+    let ring = get_ring(input);
+    let prev_corner = (2 * ring - 1) * (2 * ring - 1);
+
+    let k = ring as i64;
+    let t = (input - prev_corner - 1) as i64;
+
+    if t < 2 * k {
+        (k, -k + 1 + t)
+    } else if t < 4 * k {
+        (k - 1 - (t - 2 * k), k)
+    } else if t < 6 * k {
+        (-k, k - 1 - (t - 4 * k))
+    } else {
+        (-k + 1 + (t - 6 * k), -k)
+    }
+}
 
-    // go up the right edge
-    while y < iring {
-        y += 1;
-        n += 1;
+/// Inverse of `spiral_coords`; None if the square's number wouldn't fit in a u64
+fn spiral_index(x: i64, y: i64) -> Option<u64> {
+    let (x, y) = (x as i128, y as i128);
+    let k = x.abs().max(y.abs());
 
-        if n == input {
-            return dist(x, y);
-        }
+    if k == 0 {
+        return Some(1);
     }
 
-    // go along the top
-    while x > -iring {
-        x -= 1;
-        n += 1;
-
-        if n == input {
-            return dist(x, y);
-        }
+    // every u64 lives within ring 2^31; further out would overflow the math below
+    if k > 1 << 32 {
+        return None;
     }
 
-    // go along the left side
-    while y > -iring {
-        y -= 1;
-        n += 1;
+    let t = if x == k && y > -k {
+        y + k - 1
+    } else if y == k {
+        2 * k + (k - 1 - x)
+    } else if x == -k {
+        4 * k + (k - 1 - y)
+    } else {
+        6 * k + (x + k - 1)
+    };
 
-        if n == input {
-            return dist(x, y);
-        }
-    }
+    let prev_corner = (2 * k - 1) * (2 * k - 1);
 
-    // go along the bottom
-    while x < iring {
-        x += 1;
-        n += 1;
+    std::convert::TryFrom::try_from(prev_corner + 1 + t).ok()
+}
 
-        if n == input {
-            return dist(x, y);
-        }
-    }
+fn run_3a_with_input(input: u64) -> u64 {
+    let (x, y) = spiral_coords(input.max(1));
 
-    panic!("Didn't find the input, this shouldn't happen");
+    (x.abs() + y.abs()) as u64
 }
 
 pub fn run_3a() -> u64 {
     run_3a_with_input(INPUT.parse().unwrap())
 }
 
-/// Sadly 3a does not extend nicely to 3b
-/// This is still O(n) where n is input, but it uses a hashmap because I can't be bothered with math
-fn run_3b_with_input(input: u64) -> u64 {
-    let mut running: HashMap<(i32, i32), u64> = HashMap::new();
-
-    running.insert((0, 0), 1);
+const NEIGHBORS: [(i64, i64); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+/// What a rule gets to look at when deciding the value of a square
+struct Cell<'a> {
+    index: u64,
+    x: i64,
+    y: i64,
+    // values[n] is the value written at square n; index 0 is padding
+    values: &'a [u64],
+}
 
-    let adder = |x: i32, y: i32, map: &mut HashMap<(i32, i32), u64>| {
-        let left = map.get(&(x - 1, y)).copied().unwrap_or(0);
-        let right = map.get(&(x + 1, y)).copied().unwrap_or(0);
-        let up = map.get(&(x, y - 1)).copied().unwrap_or(0);
-        let down = map.get(&(x, y + 1)).copied().unwrap_or(0);
-        let dl = map.get(&(x - 1, y + 1)).copied().unwrap_or(0);
-        let dr = map.get(&(x + 1, y + 1)).copied().unwrap_or(0);
-        let ul = map.get(&(x - 1, y - 1)).copied().unwrap_or(0);
-        let ur = map.get(&(x + 1, y - 1)).copied().unwrap_or(0);
+impl<'a> Cell<'a> {
+    /// Values of the adjacent squares (diagonals included) which were filled in before this one
+    fn neighbor_values(&self) -> impl Iterator<Item = u64> + 'a {
+        let (index, x, y, values) = (self.index, self.x, self.y, self.values);
 
-        let total = left + right + up + down + dl + dr + ul + ur;
+        NEIGHBORS
+            .iter()
+            .filter_map(move |(dx, dy)| spiral_index(x + dx, y + dy))
+            .filter(move |&neighbor| neighbor < index)
+            .map(move |neighbor| values[neighbor as usize])
+    }
+}
 
-        map.insert((x, y), total);
+/// Lazily fills in the spiral one square at a time, in index order: the first square
+/// gets the seed and every later square is decided by the rule. The sequence ends at the
+/// first square the rule has no value for (say because it would overflow).
+struct SpiralFill<F> {
+    seed: u64,
+    values: Vec<u64>,
+    rule: F,
+}
 
-        total
-    };
+impl<F: FnMut(&Cell) -> Option<u64>> SpiralFill<F> {
+    fn new(seed: u64, rule: F) -> SpiralFill<F> {
+        SpiralFill {
+            seed,
+            values: vec![0],
+            rule,
+        }
+    }
+}
 
-    let mut ring: u32 = 0;
+impl<F: FnMut(&Cell) -> Option<u64>> Iterator for SpiralFill<F> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let index = self.values.len() as u64;
+
+        let value = if index == 1 {
+            self.seed
+        } else {
+            let (x, y) = spiral_coords(index);
+            (self.rule)(&Cell {
+                index,
+                x,
+                y,
+                values: &self.values,
+            })?
+        };
+
+        self.values.push(value);
+        Some(value)
+    }
+}
 
-    let mut x: i32 = 0;
-    let mut y: i32 = 0;
+/// None once the sum no longer fits in a u64, a little under 500 squares in
+fn neighbor_sum(cell: &Cell) -> Option<u64> {
+    cell.neighbor_values()
+        .try_fold(0_u64, |acc, v| acc.checked_add(v))
+}
 
-    loop {
-        // increment the ring
-        x += 1;
-        ring += 1;
+type CellRule = Box<dyn FnMut(&Cell) -> Option<u64>>;
+
+/// The named cell rules, along with the seed each uses unless told otherwise
+fn named_rule(name: &str) -> Option<(u64, CellRule)> {
+    match name {
+        "sum" => Some((1, Box::new(neighbor_sum))),
+        "index" => Some((1, Box::new(|cell: &Cell| Some(cell.index)))),
+        "distance" => Some((
+            0,
+            Box::new(|cell: &Cell| Some((cell.x.abs() + cell.y.abs()) as u64)),
+        )),
+        other => {
+            let modulus: u64 = other.strip_prefix("product-mod-")?.parse().ok()?;
+            if modulus == 0 {
+                return None;
+            }
 
-        let next = adder(x, y, &mut running);
-        if next > input {
-            return next;
+            Some((
+                1,
+                // the product of two values below a 64-bit modulus needs 128 bits
+                Box::new(move |cell: &Cell| {
+                    Some(cell.neighbor_values().fold(1, |acc, v| {
+                        (acc as u128 * (v % modulus) as u128 % modulus as u128) as u64
+                    }))
+                }),
+            ))
         }
+    }
+}
 
-        let iring = ring as i32;
-        // up the right edge
-        while y > -iring {
-            y -= 1;
+/// The size x size window of the filled spiral around the origin, top row first.
+/// Even sizes put the extra row and column on the top and right. Fails if the fill ends
+/// before the window is full.
+fn spiral_window<F: FnMut(&Cell) -> Option<u64>>(
+    fill: SpiralFill<F>,
+    size: usize,
+) -> Result<Vec<Vec<u64>>, String> {
+    if size == 0 {
+        return Ok(Vec::new());
+    }
 
-            let next = adder(x, y, &mut running);
-            if next > input {
-                return next;
-            }
-        }
+    let lo = -((size as i64 - 1) / 2);
+    let hi = lo + size as i64 - 1;
+
+    // the window's outermost ring is only complete at one of its corners
+    let needed = [(lo, lo), (lo, hi), (hi, lo), (hi, hi)]
+        .iter()
+        .map(|&(x, y)| spiral_index(x, y).unwrap())
+        .max()
+        .unwrap();
+
+    let values: Vec<u64> = fill.take(needed as usize).collect();
+    if values.len() < needed as usize {
+        return Err(format!(
+            "Square {} overflows 64 bits, before the window is full",
+            values.len() + 1
+        ));
+    }
 
-        // left the top edge
-        while x > -iring {
-            x -= 1;
+    Ok((lo..=hi)
+        .rev()
+        .map(|y| {
+            (lo..=hi)
+                .map(|x| values[spiral_index(x, y).unwrap() as usize - 1])
+                .collect()
+        })
+        .collect())
+}
 
-            let next = adder(x, y, &mut running);
-            if next > input {
-                return next;
-            }
-        }
+fn window_to_table(window: &[Vec<u64>]) -> String {
+    let width = window
+        .iter()
+        .flatten()
+        .map(|v| v.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    window
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|v| format!("{:>width$}", v, width = width))
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
 
-        // down the left edge
-        while y < iring {
-            y += 1;
+fn window_to_csv(window: &[Vec<u64>]) -> String {
+    window
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
 
-            let next = adder(x, y, &mut running);
-            if next > input {
-                return next;
-            }
-        }
+/// Renders the size x size window of a spiral filled by one of the named rules
+/// (sum, index, distance, product-mod-N) as an aligned table or as CSV
+pub fn render_spiral_3(rule: &str, size: usize, format: &str, seed: Option<u64>) -> String {
+    let (default_seed, rule_fn) = match named_rule(rule) {
+        Some(found) => found,
+        None => return format!("Unrecognized spiral rule {}", rule),
+    };
 
-        // right the bottom edge
-        while x < iring {
-            x += 1;
+    let window = match spiral_window(SpiralFill::new(seed.unwrap_or(default_seed), rule_fn), size) {
+        Ok(window) => window,
+        Err(e) => return e,
+    };
 
-            let next = adder(x, y, &mut running);
-            if next > input {
-                return next;
-            }
-        }
+    match format {
+        "text" => window_to_table(&window),
+        "csv" => window_to_csv(&window),
+        other => format!("Unrecognized output format {}", other),
     }
 }
 
+fn run_3b_with_input(input: u64) -> u64 {
+    SpiralFill::new(1, neighbor_sum)
+        .find(|&value| value > input)
+        .unwrap()
+}
+
 pub fn run_3b() -> u64 {
     run_3b_with_input(INPUT.parse().unwrap())
 }
@@ -197,6 +328,106 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_spiral_coords() {
+        let expected = [
+            (1, (0, 0)),
+            (2, (1, 0)),
+            (3, (1, 1)),
+            (5, (-1, 1)),
+            (7, (-1, -1)),
+            (9, (1, -1)),
+            (10, (2, -1)),
+            (13, (2, 2)),
+            (17, (-2, 2)),
+            (21, (-2, -2)),
+            (25, (2, -2)),
+        ];
+
+        for (input, coords) in expected.iter().copied() {
+            assert_eq!(spiral_coords(input), coords);
+            assert_eq!(spiral_index(coords.0, coords.1), Some(input));
+        }
+
+        for n in 1..10_000 {
+            let (x, y) = spiral_coords(n);
+            assert_eq!(spiral_index(x, y), Some(n));
+        }
+    }
+
+    #[test]
+    fn test_spiral_extremes() {
+        for n in (u64::MAX - 100..=u64::MAX).chain(1 << 62..(1 << 62) + 100) {
+            let (x, y) = spiral_coords(n);
+            assert_eq!(spiral_index(x, y), Some(n));
+        }
+
+        let (x, y) = spiral_coords(u64::MAX);
+        assert_eq!(spiral_index(x - 1, y), None);
+        assert_eq!(spiral_index(i64::MIN, i64::MAX), None);
+    }
+
+    #[test]
+    fn test_spiral_fill_oeis() {
+        // OEIS A141481, which is exactly the 3b stress test sequence
+        let expected: Vec<u64> = vec![
+            1, 1, 2, 4, 5, 10, 11, 23, 25, 26, 54, 57, 59, 122, 133, 142, 147, 304, 330, 351, 362,
+            747, 806, 880, 931, 957, 1968, 2105, 2275, 2391, 2450, 5022, 5336, 5733, 6155, 6444,
+            6591, 13486, 14267, 15252, 16295, 17008, 17370, 35487, 37402, 39835, 42452, 45220,
+            47108, 48065, 98098, 103128, 109476, 116247, 123363, 128204, 130654, 266330, 279138,
+            295229, 312453, 330785, 349975, 363010, 369601, 752688, 787032, 830037, 875851, 924406,
+            975079, 1009457, 1026827, 2089141, 2179400, 2292124, 2411813, 2539320,
+        ];
+
+        let actual: Vec<u64> = SpiralFill::new(1, neighbor_sum)
+            .take(expected.len())
+            .collect();
+
+        assert_eq!(actual, expected);
+
+        // the sums pass u64::MAX at square 479, and the sequence stops rather than wrapping
+        let sums: Vec<u64> = SpiralFill::new(1, neighbor_sum).collect();
+        assert_eq!(sums.len(), 478);
+        assert!(sums.windows(2).skip(1).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_spiral_render() {
+        assert_eq!(
+            render_spiral_3("index", 5, "text", None),
+            "17 16 15 14 13
+18  5  4  3 12
+19  6  1  2 11
+20  7  8  9 10
+21 22 23 24 25"
+        );
+
+        assert_eq!(render_spiral_3("sum", 2, "csv", None), "4,2\n1,1");
+        assert_eq!(render_spiral_3("sum", 21, "text", None).lines().count(), 21);
+        assert_eq!(
+            render_spiral_3("sum", 23, "text", None),
+            "Square 479 overflows 64 bits, before the window is full"
+        );
+        assert_eq!(
+            render_spiral_3("product-mod-5", 3, "text", Some(2)),
+            "2 1 4\n4 2 2\n3 3 2"
+        );
+
+        // reducing mod 5 * 2^61 and then mod 5 is the same as reducing mod 5 all along,
+        // as long as the big products don't overflow on the way
+        let big = 5 * (1_u64 << 61);
+        let csv_values = |rule: &str| -> Vec<u64> {
+            render_spiral_3(rule, 7, "csv", Some(3))
+                .split([',', '\n'])
+                .map(|v| v.parse().unwrap())
+                .collect()
+        };
+        let small = csv_values("product-mod-5");
+        let large = csv_values(&format!("product-mod-{}", big));
+        assert!(large.iter().any(|&v| v > 1 << 32));
+        assert_eq!(large.iter().map(|v| v % 5).collect::<Vec<_>>(), small);
+    }
+
     #[test]
     fn test_3a_samples() {
         assert_eq!(run_3a_with_input(1), 0);
@@ -1,46 +1,365 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+
+use unicode_normalization::UnicodeNormalization;
 
 const INPUT: &str = include_str!("input/4.txt");
 
-fn is_valid_4a(line: &str) -> bool {
-    let mut seen: HashSet<String> = HashSet::new();
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Rule {
+    UniqueWords,
+    NoAnagrams,
+    MinWords(usize),
+    MaxWords(usize),
+    Alphabet(Vec<char>),
+    /// Every pair of words must be at least this many single-character edits apart
+    MinEditDistance(usize),
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::UniqueWords => write!(f, "unique-words"),
+            Rule::NoAnagrams => write!(f, "no-anagrams"),
+            Rule::MinWords(n) => write!(f, "min-words {}", n),
+            Rule::MaxWords(n) => write!(f, "max-words {}", n),
+            Rule::Alphabet(chars) => {
+                // escaped the way the config wants, so it reads back as the same rule
+                let escaped: String = chars
+                    .iter()
+                    .flat_map(|&c| match c {
+                        '#' | '\\' => vec!['\\', c],
+                        c => vec![c],
+                    })
+                    .collect();
+                write!(f, "alphabet {}", escaped)
+            }
+            Rule::MinEditDistance(n) => write!(f, "min-edit-distance {}", n),
+        }
+    }
+}
+
+/// What gets done to each word before any rule looks at it
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Normalization {
+    None,
+    /// Canonical composition, so "é" typed as one character or as "e" and a combining
+    /// accent count as the same word
+    Nfc,
+    /// Compatibility composition, which also folds ligatures, full-width forms and the like
+    /// into their plain equivalents
+    Nfkc,
+    /// Unicode-aware lowercasing (then NFC), so "Abc" and "aBC" count as the same word
+    Lowercase,
+}
 
-    for token in line.split_whitespace() {
-        if !seen.insert(token.to_string()) {
-            return false;
+impl Normalization {
+    fn apply(self, word: &str) -> String {
+        match self {
+            Normalization::None => word.to_string(),
+            Normalization::Nfc => word.nfc().collect(),
+            Normalization::Nfkc => word.nfkc().collect(),
+            Normalization::Lowercase => word.to_lowercase().nfc().collect(),
         }
     }
+}
 
-    true
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct Violation {
+    rule: Rule,
+    words: Vec<String>,
 }
 
-fn is_valid_4b(line: &str) -> bool {
-    let mut seen: HashSet<String> = HashSet::new();
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct PassphrasePolicy {
+    normalization: Normalization,
+    rules: Vec<Rule>,
+}
 
-    for token in line.split_whitespace() {
-        let mut chars: Vec<char> = token.chars().collect();
-        chars.sort();
+/// The part of a config line before any comment, with `\#` standing for a literal `#`
+/// and `\\` for a backslash
+fn strip_comment(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars().peekable();
 
-        if !seen.insert(chars.into_iter().collect()) {
-            return false;
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => break,
+            '\\' if matches!(chars.peek(), Some('#') | Some('\\')) => {
+                out.push(chars.next().unwrap())
+            }
+            c => out.push(c),
         }
     }
 
-    true
+    out
+}
+
+/// Levenshtein distance, over chars rather than bytes
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().copied().enumerate() {
+            let substitute = prev[j] + if ca == cb { 0 } else { 1 };
+            curr[j + 1] = substitute.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+
+    prev[b.len()]
+}
+
+/// Words which share a key with an earlier word, each reported alongside the first
+/// word with that key
+fn collisions<K: Eq + std::hash::Hash, F: Fn(&str) -> K>(words: &[String], key: F) -> Vec<String> {
+    let mut first_seen: HashMap<K, &String> = HashMap::new();
+    let mut out: Vec<String> = Vec::new();
+
+    for word in words {
+        match first_seen.get(&key(word)) {
+            Some(first) => {
+                if !out.contains(first) {
+                    out.push((*first).clone());
+                }
+                out.push(word.clone());
+            }
+            None => {
+                first_seen.insert(key(word), word);
+            }
+        }
+    }
+
+    out
+}
+
+impl Rule {
+    /// The words which break the rule, or None if it holds. Word count rules have no
+    /// particular word to blame, so they report all of them.
+    fn check(&self, words: &[String]) -> Option<Vec<String>> {
+        let offenders = match self {
+            Rule::MinWords(n) => return Some(words.to_vec()).filter(|_| words.len() < *n),
+            Rule::MaxWords(n) => return Some(words.to_vec()).filter(|_| words.len() > *n),
+            Rule::UniqueWords => collisions(words, |w| w.to_string()),
+            Rule::NoAnagrams => collisions(words, |w| {
+                let mut chars: Vec<char> = w.chars().collect();
+                chars.sort_unstable();
+                chars
+            }),
+            Rule::Alphabet(chars) => {
+                let allowed: HashSet<char> = chars.iter().copied().collect();
+                words
+                    .iter()
+                    .filter(|w| w.chars().any(|c| !allowed.contains(&c)))
+                    .cloned()
+                    .collect()
+            }
+            Rule::MinEditDistance(n) => {
+                let mut out: Vec<String> = Vec::new();
+                for (i, a) in words.iter().enumerate() {
+                    for b in words.iter().skip(i + 1) {
+                        if edit_distance(a, b) < *n {
+                            for word in [a, b].iter() {
+                                if !out.contains(word) {
+                                    out.push((*word).clone());
+                                }
+                            }
+                        }
+                    }
+                }
+                out
+            }
+        };
+
+        Some(offenders).filter(|offenders| !offenders.is_empty())
+    }
+}
+
+impl PassphrasePolicy {
+    fn policy_4a() -> PassphrasePolicy {
+        PassphrasePolicy {
+            normalization: Normalization::None,
+            rules: vec![Rule::UniqueWords],
+        }
+    }
+
+    fn policy_4b() -> PassphrasePolicy {
+        PassphrasePolicy {
+            normalization: Normalization::None,
+            rules: vec![Rule::NoAnagrams],
+        }
+    }
+
+    /// One rule per line, `#` for comments (`\#` for a `#` that isn't one):
+    ///
+    /// ```text
+    /// normalize lowercase     # or none, nfc, nfkc
+    /// unique-words
+    /// no-anagrams
+    /// min-words 2
+    /// max-words 10
+    /// alphabet abcdefghijklmnopqrstuvwxyz
+    /// min-edit-distance 2
+    /// ```
+    fn parse(config: &str) -> Result<PassphrasePolicy, String> {
+        let mut policy = PassphrasePolicy {
+            normalization: Normalization::None,
+            rules: Vec::new(),
+        };
+
+        for (line_num, line) in config.lines().enumerate() {
+            let line = strip_comment(line);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+            let name = tokens.next().unwrap();
+            let arg = tokens.next();
+
+            if tokens.next().is_some() {
+                return Err(format!("Line {}: too many arguments", line_num + 1));
+            }
+
+            let num_arg = || -> Result<usize, String> {
+                arg.and_then(|a| a.parse().ok())
+                    .ok_or_else(|| format!("Line {}: '{}' needs a number", line_num + 1, name))
+            };
+
+            let rule = match (name, arg) {
+                ("normalize", Some("none")) => {
+                    policy.normalization = Normalization::None;
+                    continue;
+                }
+                ("normalize", Some("nfc")) => {
+                    policy.normalization = Normalization::Nfc;
+                    continue;
+                }
+                ("normalize", Some("nfkc")) => {
+                    policy.normalization = Normalization::Nfkc;
+                    continue;
+                }
+                ("normalize", Some("lowercase")) => {
+                    policy.normalization = Normalization::Lowercase;
+                    continue;
+                }
+                ("unique-words", None) => Rule::UniqueWords,
+                ("no-anagrams", None) => Rule::NoAnagrams,
+                ("min-words", _) => Rule::MinWords(num_arg()?),
+                ("max-words", _) => Rule::MaxWords(num_arg()?),
+                ("min-edit-distance", _) => Rule::MinEditDistance(num_arg()?),
+                ("alphabet", Some(chars)) => Rule::Alphabet(chars.chars().collect()),
+                _ => {
+                    return Err(format!(
+                        "Line {}: unrecognized rule '{}'",
+                        line_num + 1,
+                        line
+                    ))
+                }
+            };
+
+            policy.rules.push(rule);
+        }
+
+        // the alphabet gets the same treatment as the words it's checked against, wherever
+        // the normalize line was
+        for rule in policy.rules.iter_mut() {
+            if let Rule::Alphabet(chars) = rule {
+                let text: String = chars.iter().collect();
+                *chars = policy.normalization.apply(&text).chars().collect();
+            }
+        }
+
+        Ok(policy)
+    }
+
+    fn words(&self, line: &str) -> Vec<String> {
+        line.split_whitespace()
+            .map(|word| self.normalization.apply(word))
+            .collect()
+    }
+
+    /// Every rule the line breaks, in the order the rules were given
+    fn check(&self, line: &str) -> Vec<Violation> {
+        let words = self.words(line);
+
+        self.rules
+            .iter()
+            .filter_map(|rule| {
+                rule.check(&words).map(|words| Violation {
+                    rule: rule.clone(),
+                    words,
+                })
+            })
+            .collect()
+    }
+
+    fn is_valid(&self, line: &str) -> bool {
+        let words = self.words(line);
+
+        self.rules.iter().all(|rule| rule.check(&words).is_none())
+    }
+}
+
+/// Checks every line of the input against a policy config, saying which rules each
+/// invalid line broke and which words were to blame
+pub fn check_policy_4(config: &str, input: &str) -> String {
+    let policy = match PassphrasePolicy::parse(config) {
+        Ok(policy) => policy,
+        Err(e) => return e,
+    };
+
+    let mut out = String::new();
+    let mut valid = 0;
+    let mut total = 0;
+
+    for (line_num, line) in input.lines().enumerate() {
+        total += 1;
+
+        let violations = policy.check(line);
+        if violations.is_empty() {
+            valid += 1;
+            continue;
+        }
+
+        out.push_str(&format!("Line {}: {}\n", line_num + 1, line));
+        for violation in violations {
+            out.push_str(&format!(
+                "  {}: {}\n",
+                violation.rule,
+                violation.words.join(" ")
+            ));
+        }
+    }
+
+    out.push_str(&format!("{} of {} passphrases are valid", valid, total));
+    out
 }
 
 pub fn run_4a() -> usize {
-    INPUT.lines().filter(|line| is_valid_4a(line)).count()
+    let policy = PassphrasePolicy::policy_4a();
+    INPUT.lines().filter(|line| policy.is_valid(line)).count()
 }
 
 pub fn run_4b() -> usize {
-    INPUT.lines().filter(|line| is_valid_4b(line)).count()
+    let policy = PassphrasePolicy::policy_4b();
+    INPUT.lines().filter(|line| policy.is_valid(line)).count()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn is_valid_4a(line: &str) -> bool {
+        PassphrasePolicy::policy_4a().is_valid(line)
+    }
+
+    fn is_valid_4b(line: &str) -> bool {
+        PassphrasePolicy::policy_4b().is_valid(line)
+    }
+
     #[test]
     pub fn samples_4a() {
         assert!(is_valid_4a("aa bb cc dd ee"));
@@ -56,4 +375,116 @@ mod tests {
         assert!(is_valid_4b("iiii oiii ooii oooi oooo"));
         assert!(!is_valid_4b("oiii ioii iioi iiio"));
     }
+
+    #[test]
+    pub fn composed_policy() {
+        let config = "# stricter than the puzzle
+normalize lowercase
+unique-words
+min-words 3
+max-words 4
+alphabet abcdefghijklmnopqrstuvwxyz
+min-edit-distance 2  # no near-duplicates
+";
+
+        let policy = PassphrasePolicy::parse(config).unwrap();
+
+        assert!(policy.check("alpha bravo charlie").is_empty());
+        assert_eq!(
+            policy.check("Alpha alpha"),
+            vec![
+                Violation {
+                    rule: Rule::UniqueWords,
+                    words: vec!["alpha".to_string(), "alpha".to_string()],
+                },
+                Violation {
+                    rule: Rule::MinWords(3),
+                    words: vec!["alpha".to_string(), "alpha".to_string()],
+                },
+                Violation {
+                    rule: Rule::MinEditDistance(2),
+                    words: vec!["alpha".to_string()],
+                },
+            ]
+        );
+        assert_eq!(
+            policy.check("cart card x-ray"),
+            vec![
+                Violation {
+                    rule: Rule::Alphabet("abcdefghijklmnopqrstuvwxyz".chars().collect()),
+                    words: vec!["x-ray".to_string()],
+                },
+                Violation {
+                    rule: Rule::MinEditDistance(2),
+                    words: vec!["cart".to_string(), "card".to_string()],
+                },
+            ]
+        );
+        assert_eq!(policy.check("").len(), 1);
+
+        assert!(PassphrasePolicy::parse("min-words lots").is_err());
+        assert!(PassphrasePolicy::parse("no-palindromes").is_err());
+    }
+
+    #[test]
+    pub fn unicode_normalization() {
+        // "café" with a precomposed é, and with e + a combining acute accent
+        let line = "caf\u{e9} cafe\u{301}";
+
+        let policy = PassphrasePolicy::parse("unique-words").unwrap();
+        assert!(policy.check(line).is_empty());
+
+        let policy = PassphrasePolicy::parse("normalize nfc\nunique-words").unwrap();
+        assert_eq!(
+            policy.check(line),
+            vec![Violation {
+                rule: Rule::UniqueWords,
+                words: vec!["caf\u{e9}".to_string(), "caf\u{e9}".to_string()],
+            }]
+        );
+
+        // the fi ligature is only compatibility-equivalent to "fi"
+        assert!(policy.check("\u{fb01}ne fine").is_empty());
+        let policy = PassphrasePolicy::parse("normalize nfkc\nunique-words").unwrap();
+        assert_eq!(policy.check("\u{fb01}ne fine").len(), 1);
+
+        let policy = PassphrasePolicy::parse("normalize lowercase\nunique-words").unwrap();
+        assert_eq!(policy.check("CAFE\u{301} caf\u{e9}").len(), 1);
+
+        // a decomposed alphabet still lets the composed letter through
+        let policy = PassphrasePolicy::parse("alphabet cafe\u{301}\nnormalize nfc").unwrap();
+        assert!(policy.check("cafe\u{301} caf\u{e9}").is_empty());
+    }
+
+    #[test]
+    pub fn escaped_comments() {
+        let policy = PassphrasePolicy::parse("alphabet ab\\#\\\\  # but not c").unwrap();
+        assert_eq!(
+            policy.rules,
+            vec![Rule::Alphabet(vec!['a', 'b', '#', '\\'])]
+        );
+        assert!(policy.check("a#b \\b").is_empty());
+        assert_eq!(policy.rules[0].to_string(), "alphabet ab\\#\\\\");
+        assert_eq!(
+            PassphrasePolicy::parse(&policy.rules[0].to_string()).unwrap(),
+            policy
+        );
+        assert_eq!(policy.check("abc").len(), 1);
+
+        // any other backslash is just a backslash
+        assert_eq!(strip_comment("x\\y # z"), "x\\y ");
+        assert_eq!(strip_comment("\\"), "\\");
+    }
+
+    #[test]
+    pub fn policy_report() {
+        let report = check_policy_4("no-anagrams", "abcde fghij\nabcde xyz ecdab");
+
+        assert_eq!(
+            report,
+            "Line 2: abcde xyz ecdab
+  no-anagrams: abcde ecdab
+1 of 2 passphrases are valid"
+        );
+    }
 }
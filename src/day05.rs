@@ -1,107 +1,634 @@
 const INPUT: &str = include_str!("input/5.txt");
 
-#[derive(Clone, Eq, PartialEq)]
-struct VM {
+/// Small expression language for offset-update rules, in terms of the old offset `x`:
+/// `x + 1`, `x >= 3 ? x - 1 : x + 1`, `(x * 2) % 7 - 3`, and so on.
+/// Arithmetic wraps rather than overflowing; dividing by zero leaves the rule without an answer.
+mod rule {
+    use nom::{
+        branch::alt,
+        bytes::complete::tag,
+        character::complete::{char, multispace0},
+        multi::many0,
+        sequence::{delimited, pair, preceded, tuple},
+        IResult, Parser,
+    };
+
+    use crate::lib::parse_i64 as parse_num;
+
+    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+    pub(super) enum BinOp {
+        Add,
+        Sub,
+        Mul,
+        Div,
+        Rem,
+    }
+
+    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+    pub(super) enum CmpOp {
+        Gt,
+        Lt,
+        Geq,
+        Leq,
+        Eq,
+        Neq,
+    }
+
+    #[derive(Clone, Eq, PartialEq, Debug)]
+    pub(super) enum RuleExpr {
+        Offset,
+        Const(isize),
+        Neg(Box<RuleExpr>),
+        Bin(BinOp, Box<RuleExpr>, Box<RuleExpr>),
+        Cond(
+            CmpOp,
+            Box<RuleExpr>,
+            Box<RuleExpr>,
+            Box<RuleExpr>,
+            Box<RuleExpr>,
+        ),
+    }
+
+    impl RuleExpr {
+        /// The new offset for old offset `x`, or None if working it out divides by zero
+        pub(super) fn eval(&self, x: isize) -> Option<isize> {
+            match self {
+                RuleExpr::Offset => Some(x),
+                RuleExpr::Const(c) => Some(*c),
+                RuleExpr::Neg(inner) => inner.eval(x).map(isize::wrapping_neg),
+                RuleExpr::Bin(op, lhs, rhs) => {
+                    let (lhs, rhs) = (lhs.eval(x)?, rhs.eval(x)?);
+                    match op {
+                        BinOp::Add => Some(lhs.wrapping_add(rhs)),
+                        BinOp::Sub => Some(lhs.wrapping_sub(rhs)),
+                        BinOp::Mul => Some(lhs.wrapping_mul(rhs)),
+                        BinOp::Div if rhs == 0 => None,
+                        BinOp::Div => Some(lhs.wrapping_div(rhs)),
+                        BinOp::Rem if rhs == 0 => None,
+                        BinOp::Rem => Some(lhs.wrapping_rem(rhs)),
+                    }
+                }
+                RuleExpr::Cond(op, lhs, rhs, if_true, if_false) => {
+                    let (lhs, rhs) = (lhs.eval(x)?, rhs.eval(x)?);
+                    let holds = match op {
+                        CmpOp::Gt => lhs > rhs,
+                        CmpOp::Lt => lhs < rhs,
+                        CmpOp::Geq => lhs >= rhs,
+                        CmpOp::Leq => lhs <= rhs,
+                        CmpOp::Eq => lhs == rhs,
+                        CmpOp::Neq => lhs != rhs,
+                    };
+                    if holds {
+                        if_true.eval(x)
+                    } else {
+                        if_false.eval(x)
+                    }
+                }
+            }
+        }
+    }
+
+    fn ws<'a, O, P: Parser<&'a str, O, nom::error::Error<&'a str>>>(
+        parser: P,
+    ) -> impl FnMut(&'a str) -> IResult<&'a str, O> {
+        delimited(multispace0, parser, multispace0)
+    }
+
+    fn parse_factor(input: &str) -> IResult<&str, RuleExpr> {
+        alt((
+            ws(char('x')).map(|_| RuleExpr::Offset),
+            ws(parse_num).map(|c| RuleExpr::Const(c as isize)),
+            ws(delimited(char('('), parse_expr, char(')'))),
+            preceded(ws(char('-')), parse_factor).map(|inner| RuleExpr::Neg(Box::new(inner))),
+        ))(input)
+    }
+
+    fn fold_binary(first: RuleExpr, rest: Vec<(BinOp, RuleExpr)>) -> RuleExpr {
+        rest.into_iter().fold(first, |lhs, (op, rhs)| {
+            RuleExpr::Bin(op, Box::new(lhs), Box::new(rhs))
+        })
+    }
+
+    fn parse_term(input: &str) -> IResult<&str, RuleExpr> {
+        let op = alt((
+            char('*').map(|_| BinOp::Mul),
+            char('/').map(|_| BinOp::Div),
+            char('%').map(|_| BinOp::Rem),
+        ));
+
+        pair(parse_factor, many0(pair(op, parse_factor)))
+            .map(|(first, rest)| fold_binary(first, rest))
+            .parse(input)
+    }
+
+    fn parse_arith(input: &str) -> IResult<&str, RuleExpr> {
+        let op = alt((char('+').map(|_| BinOp::Add), char('-').map(|_| BinOp::Sub)));
+
+        pair(parse_term, many0(pair(op, parse_term)))
+            .map(|(first, rest)| fold_binary(first, rest))
+            .parse(input)
+    }
+
+    fn parse_cond(input: &str) -> IResult<&str, RuleExpr> {
+        // Note: order matters, these branches are applied in order
+        let op = alt((
+            tag(">=").map(|_| CmpOp::Geq),
+            tag(">").map(|_| CmpOp::Gt),
+            tag("<=").map(|_| CmpOp::Leq),
+            tag("<").map(|_| CmpOp::Lt),
+            tag("==").map(|_| CmpOp::Eq),
+            tag("!=").map(|_| CmpOp::Neq),
+        ));
+
+        let (input, (lhs, op, rhs, _, if_true, _, if_false)) = tuple((
+            parse_arith,
+            op,
+            parse_arith,
+            char('?'),
+            parse_expr,
+            char(':'),
+            parse_expr,
+        ))(input)?;
+
+        Ok((
+            input,
+            RuleExpr::Cond(
+                op,
+                Box::new(lhs),
+                Box::new(rhs),
+                Box::new(if_true),
+                Box::new(if_false),
+            ),
+        ))
+    }
+
+    fn parse_expr(input: &str) -> IResult<&str, RuleExpr> {
+        alt((parse_cond, parse_arith))(input)
+    }
+
+    pub(super) fn parse(input: &str) -> Result<RuleExpr, String> {
+        match parse_expr(input) {
+            Ok(("", expr)) => Ok(expr),
+            Ok((leftover, _)) => Err(format!("Could not parse rule at '{}'", leftover)),
+            Err(e) => Err(format!("Could not parse rule '{}': {:?}", input, e)),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_parse_rules() {
+            let cases: [(&str, [isize; 4]); 5] = [
+                ("x+1", [-4, 1, 4, 6]),
+                ("x >= 3 ? x - 1 : x + 1", [-4, 1, 2, 4]),
+                ("-x", [5, 0, -3, -5]),
+                ("(x * 2) % 7 - 3", [-6, -3, 3, 0]),
+                ("x < 0 ? 0 : x > 2 ? 2 : x", [0, 0, 2, 2]),
+            ];
+
+            for (text, expected) in cases.iter() {
+                let expr = parse(text).unwrap();
+                let actual: Vec<isize> = [-5, 0, 3, 5]
+                    .iter()
+                    .map(|&x| expr.eval(x).unwrap())
+                    .collect();
+                assert_eq!(&actual, expected, "Rule {}", text);
+            }
+
+            let expr = parse("x > 0 ? 12 / x : x % (x + 1)").unwrap();
+            assert_eq!(expr.eval(4), Some(3));
+            assert_eq!(expr.eval(-2), Some(0));
+            assert_eq!(expr.eval(-1), None);
+            assert_eq!(parse("-(1 / (x - x))").unwrap().eval(7), None);
+
+            assert!(parse("x +").is_err());
+            assert!(parse("y").is_err());
+        }
+    }
+}
+
+/// How a run of the maze ended
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Outcome {
+    /// Jumped outside the maze
+    Escaped,
+    /// Came back to a position and maze it had already been in, so it would go forever
+    Loops { length: usize },
+    /// Still going when the step budget ran out
+    GaveUp,
+    /// The rule had no new value for this offset
+    RuleFailed { offset: isize },
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct Report {
+    outcome: Outcome,
+    steps: usize,
+    min_offset: isize,
+    max_offset: isize,
+    jumps: Vec<isize>,
+}
+
+/// Without a step limit, how often (at least) a run stops to check whether it's been in
+/// exactly this state before
+const LOOP_CHECK_EVERY: usize = 4096;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct JumpMaze {
     ip: isize,
     jumps: Vec<isize>,
 }
 
-impl VM {
-    fn new(jumps: Vec<isize>) -> VM {
-        Self { ip: 0, jumps }
+impl JumpMaze {
+    fn new(jumps: Vec<isize>) -> JumpMaze {
+        JumpMaze { ip: 0, jumps }
     }
 
-    fn update_5a(&mut self) -> Result<(), ()> {
-        if self.ip < 0 {
-            return Err(());
-        }
+    fn parse(input: &str) -> JumpMaze {
+        let jumps: Vec<isize> = input
+            .split_whitespace()
+            .map(|token| token.parse().unwrap())
+            .collect();
+
+        JumpMaze::new(jumps)
+    }
+
+    fn escaped(&self) -> bool {
+        self.ip < 0 || self.ip as usize >= self.jumps.len()
+    }
 
+    /// Takes the jump at `ip`, rewriting its offset with `rule`, and returns the new offset
+    fn step<F: FnMut(isize) -> Option<isize>>(&mut self, rule: &mut F) -> Result<isize, Outcome> {
         let this_ip = self.ip as usize;
-        if this_ip >= self.jumps.len() {
-            return Err(());
-        }
+        let old = self.jumps[this_ip];
+        let new = rule(old).ok_or(Outcome::RuleFailed { offset: old })?;
 
-        self.ip += self.jumps[this_ip];
-        self.jumps[this_ip] += 1;
+        self.jumps[this_ip] = new;
+        // overflowing either way leaves the maze just as surely as -1 does
+        self.ip = self.ip.checked_add(old).unwrap_or(-1);
 
-        Ok(())
+        Ok(new)
     }
 
-    fn update_5b(&mut self) -> Result<(), ()> {
-        if self.ip < 0 {
-            return Err(());
-        }
+    /// Follows the jumps until they lead out of the maze, rewriting each offset with
+    /// `rule` after it's used.
+    ///
+    /// Non-termination is caught with Brent's teleporting tortoise (as in `crate::cycles`),
+    /// but only looking at every `check_every`th state, since comparing two mazes costs as
+    /// much as `check_every` steps. Those samples repeat with a period that is some multiple
+    /// of the loop's, which is then pinned down exactly. `max_steps` is just a hard cap on
+    /// top of that, for mazes that go on a long time without repeating.
+    fn run<F: FnMut(isize) -> Option<isize>>(
+        mut self,
+        mut rule: F,
+        max_steps: Option<usize>,
+    ) -> Report {
+        let mut steps = 0;
+        let mut min_offset = self.jumps.iter().copied().min().unwrap_or(0);
+        let mut max_offset = self.jumps.iter().copied().max().unwrap_or(0);
+
+        let check_every = self.jumps.len().max(LOOP_CHECK_EVERY);
+        let mut power = 1;
+        let mut checks = 0;
+        let mut tortoise = self.clone();
+
+        let outcome = loop {
+            if self.escaped() {
+                break Outcome::Escaped;
+            }
+
+            if max_steps.is_some_and(|max| steps >= max) {
+                break Outcome::GaveUp;
+            }
+
+            match self.step(&mut rule) {
+                Ok(new) => {
+                    min_offset = min_offset.min(new);
+                    max_offset = max_offset.max(new);
+                }
+                Err(outcome) => break outcome,
+            }
+
+            steps += 1;
 
-        let jump_trans = |old| {
-            if old >= 3 {
-                old - 1
-            } else {
-                old + 1
+            if steps % check_every != 0 {
+                continue;
+            }
+
+            checks += 1;
+            if self == tortoise {
+                let length = self.clone().loop_length(&mut rule, checks * check_every);
+                break Outcome::Loops { length };
+            }
+
+            if checks == power {
+                tortoise = self.clone();
+                power *= 2;
+                checks = 0;
             }
         };
 
-        let this_ip = self.ip as usize;
-        if this_ip >= self.jumps.len() {
-            return Err(());
+        Report {
+            outcome,
+            steps,
+            min_offset,
+            max_offset,
+            jumps: self.jumps,
+        }
+    }
+
+    /// The shortest loop through this state, given that it comes back round after `period`
+    /// steps
+    fn loop_length<F: FnMut(isize) -> Option<isize>>(
+        mut self,
+        rule: &mut F,
+        period: usize,
+    ) -> usize {
+        let start = self.clone();
+
+        for length in 1..period {
+            if self.step(rule).is_err() {
+                break;
+            }
+            if self.ip == start.ip && self == start {
+                return length;
+            }
+        }
+
+        period
+    }
+}
+
+fn run_5a_with_inputs(jumps: &str) -> usize {
+    JumpMaze::parse(jumps).run(|old| Some(old + 1), None).steps
+}
+
+pub fn run_5a() -> usize {
+    run_5a_with_inputs(INPUT)
+}
+
+fn update_5b(old: isize) -> isize {
+    if old >= 3 {
+        old - 1
+    } else {
+        old + 1
+    }
+}
+
+/// Special-purpose runner for the 5b rule, which takes tens of millions of steps.
+///
+/// Two tricks: offsets are stored as small integers (i8 if they all fit, else i32), and
+/// once a cell reaches 2 or 3 it can only ever flip between the two, with every jump out
+/// of it going forward. So the run of such cells at the start of the maze is packed into
+/// one bit per cell, and a lookup table crosses it eight cells at a time.
+mod fast_5b {
+    use std::convert::TryFrom;
+
+    /// Cells per byte of the saturated prefix
+    const CHUNK: usize = 8;
+
+    pub(super) trait CompactOffset: Copy {
+        fn from_isize(val: isize) -> Option<Self>;
+        fn to_isize(self) -> isize;
+    }
+
+    impl CompactOffset for i8 {
+        fn from_isize(val: isize) -> Option<i8> {
+            i8::try_from(val).ok()
         }
 
-        self.ip += self.jumps[this_ip];
-        self.jumps[this_ip] = jump_trans(self.jumps[this_ip]);
+        fn to_isize(self) -> isize {
+            self as isize
+        }
+    }
+
+    impl CompactOffset for i32 {
+        fn from_isize(val: isize) -> Option<i32> {
+            i32::try_from(val).ok()
+        }
+
+        fn to_isize(self) -> isize {
+            self as isize
+        }
+    }
 
-        Ok(())
+    /// What happens entering a chunk of saturated cells at some position with some bits
+    /// (1 for a cell holding 3, 0 for 2): the bits left behind, how many steps it took,
+    /// and how far past the end of the chunk it came out
+    #[derive(Copy, Clone, Default)]
+    struct Crossing {
+        bits: u8,
+        steps: u8,
+        overshoot: u8,
     }
 
-    fn run_until_dead_5a(mut self) -> usize {
-        let mut jumps = 0;
+    fn crossing_table() -> Vec<Crossing> {
+        let mut table = vec![Crossing::default(); CHUNK * 256];
+
+        for entry in 0..CHUNK {
+            for start_bits in 0..256_usize {
+                let mut pos = entry;
+                let mut bits = start_bits as u8;
+                let mut steps = 0;
+
+                while pos < CHUNK {
+                    let jump = 2 + ((bits >> pos) & 1) as usize;
+                    bits ^= 1 << pos;
+                    pos += jump;
+                    steps += 1;
+                }
 
-        while let Ok(_) = self.update_5a() {
-            jumps += 1;
+                table[entry * 256 + start_bits] = Crossing {
+                    bits,
+                    steps,
+                    overshoot: (pos - CHUNK) as u8,
+                };
+            }
         }
 
-        jumps
+        table
     }
 
-    fn run_until_dead_5b(mut self) -> usize {
-        let mut jumps = 0;
+    /// Packs a chunk of cells into bits, if every one of them is saturated
+    fn pack_chunk<T: CompactOffset>(cells: &[T]) -> Option<u8> {
+        let mut bits = 0;
+        for (i, cell) in cells.iter().enumerate() {
+            match cell.to_isize() {
+                2 => {}
+                3 => bits |= 1 << i,
+                _ => return None,
+            }
+        }
+        Some(bits)
+    }
+
+    fn run<T: CompactOffset>(mut cells: Vec<T>) -> usize {
+        let table = crossing_table();
+        let len = cells.len();
+
+        // prefix[c] holds the bits of cells c*CHUNK .. (c+1)*CHUNK, once they're all saturated
+        let mut prefix: Vec<u8> = Vec::with_capacity(len / CHUNK);
+
+        let mut ip: isize = 0;
+        let mut steps = 0;
+
+        while ip >= 0 && (ip as usize) < len {
+            let pos = ip as usize;
+            let chunk = pos / CHUNK;
+
+            if chunk < prefix.len() {
+                // jumps out of saturated cells only go 2 or 3 forward, so every crossing
+                // comes out near the start of the very next chunk, and the rest of the prefix
+                // can be swept in order without working out `ip` in between
+                let mut entry = pos % CHUNK;
+                for bits in prefix[chunk..].iter_mut() {
+                    let crossing = table[entry * 256 + *bits as usize];
+
+                    *bits = crossing.bits;
+                    steps += crossing.steps as usize;
+                    entry = crossing.overshoot as usize;
+                }
+
+                ip = (prefix.len() * CHUNK + entry) as isize;
+                continue;
+            }
 
-        while let Ok(_) = self.update_5b() {
-            jumps += 1;
+            // SAFETY: 0 <= pos < len is the loop condition
+            let old = unsafe { cells.get_unchecked(pos) }.to_isize();
+            let new = super::update_5b(old);
+
+            // the 5b rule only moves offsets towards 2 and 3, so they never leave the range
+            // the input started in, and still fit in whatever type held them
+            unsafe {
+                *cells.get_unchecked_mut(pos) = T::from_isize(new).unwrap();
+            }
+
+            ip += old;
+            steps += 1;
+
+            if chunk == prefix.len() {
+                while let Some(bits) = cells
+                    .get(prefix.len() * CHUNK..(prefix.len() + 1) * CHUNK)
+                    .and_then(pack_chunk)
+                {
+                    prefix.push(bits);
+                }
+            }
+        }
+
+        steps
+    }
+
+    /// Step count for the 5b rule, or None if the offsets don't fit in an i32
+    pub(super) fn steps_5b(jumps: &[isize]) -> Option<usize> {
+        if let Some(cells) = jumps.iter().map(|&j| i8::from_isize(j)).collect() {
+            return Some(run::<i8>(cells));
         }
 
         jumps
+            .iter()
+            .map(|&j| i32::from_isize(j))
+            .collect::<Option<Vec<i32>>>()
+            .map(run)
     }
 }
 
-fn run_5a_with_inputs(jumps: &str) -> usize {
-    let jumps: Vec<isize> = jumps
-        .split_whitespace()
-        .map(|token| token.parse().unwrap())
-        .collect();
-
-    let vm = VM::new(jumps);
+fn run_5b_with_inputs(jumps: &str) -> usize {
+    let maze = JumpMaze::parse(jumps);
 
-    vm.run_until_dead_5a()
+    fast_5b::steps_5b(&maze.jumps)
+        .unwrap_or_else(|| maze.run(|old| Some(update_5b(old)), None).steps)
 }
 
-pub fn run_5a() -> usize {
-    run_5a_with_inputs(INPUT)
+/// The plain step-at-a-time loop part B was first solved with, kept as a baseline
+fn plain_5b(mut jumps: Vec<isize>) -> usize {
+    let mut ip: isize = 0;
+    let mut steps = 0;
+
+    while ip >= 0 && (ip as usize) < jumps.len() {
+        let this_ip = ip as usize;
+        ip += jumps[this_ip];
+        jumps[this_ip] = update_5b(jumps[this_ip]);
+        steps += 1;
+    }
+
+    steps
 }
 
-fn run_5b_with_inputs(jumps: &str) -> usize {
-    let jumps: Vec<isize> = jumps
-        .split_whitespace()
-        .map(|token| token.parse().unwrap())
-        .collect();
+/// Times the plain loop and the general-purpose engine against the special-purpose 5b
+/// runner; speedups are the fast runner's, relative to each
+pub fn bench_5b(input: &str, runs: usize) -> String {
+    let maze = JumpMaze::parse(input);
+    let runs = runs.max(1);
 
-    let vm = VM::new(jumps);
+    let time = |run: &dyn Fn() -> Option<usize>| {
+        let start = std::time::Instant::now();
+        let mut steps = None;
+        for _ in 0..runs {
+            steps = run();
+        }
+        (steps, start.elapsed().as_secs_f64() / runs as f64)
+    };
 
-    vm.run_until_dead_5b()
+    let (plain_steps, plain_time) = time(&|| Some(plain_5b(maze.jumps.clone())));
+    let (engine_steps, engine_time) =
+        time(&|| Some(maze.clone().run(|old| Some(update_5b(old)), None).steps));
+    let (fast_steps, fast_time) = time(&|| fast_5b::steps_5b(&maze.jumps));
+
+    match fast_steps {
+        Some(fast_steps) => {
+            assert_eq!(plain_steps, Some(fast_steps), "Runners disagree");
+            assert_eq!(engine_steps, Some(fast_steps), "Runners disagree");
+            format!(
+                "{} steps\nPlain:  {:.4}s per run ({:.1}x)\nEngine: {:.4}s per run ({:.1}x)\nFast:   {:.4}s per run",
+                fast_steps,
+                plain_time,
+                plain_time / fast_time,
+                engine_time,
+                engine_time / fast_time,
+                fast_time
+            )
+        }
+        None => "Offsets too large for the fast runner".to_string(),
+    }
 }
 
 pub fn run_5b() -> usize {
     run_5b_with_inputs(INPUT)
 }
 
+/// Runs a maze with an offset-update rule written in the little rule language
+pub fn run_maze_5(input: &str, rule_text: &str, max_steps: Option<usize>) -> String {
+    let expr = match rule::parse(rule_text) {
+        Ok(expr) => expr,
+        Err(e) => return e,
+    };
+
+    let report = JumpMaze::parse(input).run(|old| expr.eval(old), max_steps);
+
+    let outcome = match report.outcome {
+        Outcome::Escaped => "Escaped the maze".to_string(),
+        Outcome::Loops { length } => format!("Stuck in a loop of {} steps", length),
+        Outcome::GaveUp => "Still going when the step limit ran out".to_string(),
+        Outcome::RuleFailed { offset } => {
+            format!("The rule divided by zero on offset {}", offset)
+        }
+    };
+
+    let jumps: Vec<String> = report.jumps.iter().map(|j| j.to_string()).collect();
+
+    format!(
+        "{} after {} steps\nOffsets ranged from {} to {}\nFinal maze: {}",
+        outcome,
+        report.steps,
+        report.min_offset,
+        report.max_offset,
+        jumps.join(" ")
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -115,4 +642,92 @@ mod tests {
     fn sample_5b() {
         assert_eq!(run_5b_with_inputs("0 3 0 1 -3"), 10);
     }
+
+    #[test]
+    fn sample_5_report() {
+        let report = JumpMaze::parse("0 3 0 1 -3").run(|old| Some(old + 1), None);
+
+        assert_eq!(
+            report,
+            Report {
+                outcome: Outcome::Escaped,
+                steps: 5,
+                min_offset: -3,
+                max_offset: 5,
+                jumps: vec![2, 5, 0, 1, -2],
+            }
+        );
+
+        let report = JumpMaze::parse("0 3 0 1 -3").run(|old| Some(old + 1), Some(3));
+        assert_eq!(report.outcome, Outcome::GaveUp);
+        assert_eq!(report.steps, 3);
+    }
+
+    #[test]
+    fn fast_5b_matches_engine() {
+        let mut mazes: Vec<Vec<isize>> = vec![
+            vec![0, 3, 0, 1, -3],
+            vec![],
+            vec![-1],
+            vec![200, 0, -150],
+            vec![100_000, -3, 2, 2, 3, 3, 2, -6],
+        ];
+
+        // something like the real input: lots of cells, mostly jumping backwards
+        for size in [50, 500, 1000].iter().copied() {
+            mazes.push(
+                (0..size as isize)
+                    .map(|i| (i * 7919 + 13) % (i + 3) - i / 2)
+                    .collect(),
+            );
+        }
+
+        for jumps in mazes {
+            let expected = JumpMaze::new(jumps.clone())
+                .run(|old| Some(update_5b(old)), None)
+                .steps;
+            assert_eq!(plain_5b(jumps.clone()), expected);
+            assert_eq!(
+                fast_5b::steps_5b(&jumps),
+                Some(expected),
+                "Maze {:?}",
+                jumps
+            );
+        }
+
+        assert_eq!(fast_5b::steps_5b(&[isize::MAX]), None);
+    }
+
+    #[test]
+    fn detects_loops() {
+        // bounces between the two cells forever without changing anything
+        let report = JumpMaze::parse("1 -1").run(Some, None);
+        assert_eq!(report.outcome, Outcome::Loops { length: 2 });
+
+        let report = JumpMaze::parse("1 1 -2 7").run(Some, None);
+        assert_eq!(report.outcome, Outcome::Loops { length: 3 });
+
+        // a step limit doesn't stop loops being found, as long as it leaves time to check
+        let report = JumpMaze::parse("1 1 -2 7").run(Some, Some(100_000));
+        assert_eq!(report.outcome, Outcome::Loops { length: 3 });
+        assert!(report.steps < 100_000);
+
+        let report = JumpMaze::parse("1 -1").run(Some, Some(10));
+        assert_eq!(report.outcome, Outcome::GaveUp);
+        assert_eq!(report.steps, 10);
+
+        assert_eq!(
+            run_maze_5("2 0 -1 -1", "x < 0 ? x - 1 : 6 / x", None),
+            "The rule divided by zero on offset 0 after 2 steps
+Offsets ranged from -2 to 3
+Final maze: 3 0 -2 -1"
+        );
+
+        assert_eq!(
+            run_maze_5("0 3 0 1 -3", "x >= 3 ? x - 1 : x + 1", None),
+            "Escaped the maze after 10 steps
+Offsets ranged from -3 to 3
+Final maze: 2 3 2 3 -1"
+        );
+    }
 }
@@ -1,19 +1,57 @@
-use std::collections::{HashMap, HashSet};
+use crate::cycles::{find_cycle, Cycle, Method};
 
-const INPUT: &str = "11	11	13	7	0	15	5	5	4	4	1	1	7	1	15	11";
+const INPUT: &str = include_str!("input/6.txt");
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 struct VM {
-    blocks: Vec<u32>,
+    blocks: Vec<u64>,
 }
 
 impl VM {
+    /// Empties the fullest bank (lowest index wins ties) and deals its blocks out one at a
+    /// time starting from the next bank. Rather than walking block by block, every bank gets
+    /// the same number of full laps and the remainder goes to the banks right after it.
     fn step(&mut self) {
         let len = self.blocks.len();
 
         let mut best_ind = 0;
         let mut best_weight = 0;
 
+        for (i, this_weight) in self.blocks.iter().copied().enumerate() {
+            if this_weight > best_weight {
+                best_weight = this_weight;
+                best_ind = i;
+            }
+        }
+
+        if best_weight == 0 {
+            return;
+        }
+
+        self.blocks[best_ind] = 0;
+
+        let laps = best_weight / len as u64;
+        let remainder = (best_weight % len as u64) as usize;
+
+        if laps > 0 {
+            for block in self.blocks.iter_mut() {
+                *block += laps;
+            }
+        }
+
+        for offset in 1..=remainder {
+            self.blocks[(best_ind + offset) % len] += 1;
+        }
+    }
+
+    /// The original one-block-at-a-time redistribution, to check the fast one against
+    #[cfg(test)]
+    fn step_slow(&mut self) {
+        let len = self.blocks.len();
+
+        let mut best_ind = 0;
+        let mut best_weight = 0;
+
         for i in 0..len {
             let this_weight = self.blocks[i];
             if this_weight > best_weight {
@@ -35,37 +73,81 @@ impl VM {
     }
 }
 
-fn run_6a_with_input(input: &str) -> usize {
-    let blocks = input
-        .split_whitespace()
-        .map(|t| t.parse::<u32>().unwrap())
-        .collect();
-    let mut vm = VM { blocks };
+/// xorshift64*; plenty random for making up bank configurations, and keeps us dependency-free
+struct XorShift {
+    state: u64,
+}
 
-    let mut seen = HashSet::new();
-    while seen.insert(vm.clone()) {
-        vm.step();
+impl XorShift {
+    fn new(seed: u64) -> XorShift {
+        // the all-zero state is a fixed point, so nudge it away
+        XorShift {
+            state: seed ^ 0x9E37_79B9_7F4A_7C15,
+        }
     }
 
-    seen.len()
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
 }
 
-fn run_6b_with_input(input: &str) -> usize {
+/// A random tab-separated line of `banks` banks holding up to `max_blocks` blocks each,
+/// in the same format as the puzzle input
+pub fn generate_banks_6(banks: usize, max_blocks: u64, seed: u64) -> String {
+    let mut rng = XorShift::new(seed);
+
+    (0..banks)
+        .map(|_| match max_blocks.checked_add(1) {
+            Some(bound) => rng.next() % bound,
+            None => rng.next(),
+        })
+        .map(|blocks| blocks.to_string())
+        .collect::<Vec<String>>()
+        .join("\t")
+}
+
+fn parse_vm(input: &str) -> VM {
     let blocks = input
         .split_whitespace()
-        .map(|t| t.parse::<u32>().unwrap())
+        .map(|t| t.parse::<u64>().unwrap())
         .collect();
-    let mut vm = VM { blocks };
 
-    let mut seen = HashMap::new();
+    VM { blocks }
+}
 
-    loop {
-        let old = seen.insert(vm.clone(), seen.len());
-        if let Some(old_steps) = old {
-            return seen.len() - old_steps;
-        }
-        vm.step();
-    }
+fn find_vm_cycle(method: Method, vm: &VM) -> Cycle {
+    find_cycle(method, vm, |vm| {
+        let mut next = vm.clone();
+        next.step();
+        next
+    })
+}
+
+fn run_6a_with_input(input: &str) -> usize {
+    let cycle = find_vm_cycle(Method::Brent, &parse_vm(input));
+
+    // every state before the first repeat is distinct
+    cycle.mu + cycle.lambda
+}
+
+fn run_6b_with_input(input: &str) -> usize {
+    find_vm_cycle(Method::Brent, &parse_vm(input)).lambda
+}
+
+/// Tail and loop length of the reallocation sequence, found with the given method
+pub fn cycle_6(input: &str, method: Method) -> String {
+    let cycle = find_vm_cycle(method, &parse_vm(input));
+    let steps = |n: usize| format!("{} step{}", n, if n == 1 { "" } else { "s" });
+
+    format!(
+        "Tail of {}, first repeat after {}, looping every {}",
+        steps(cycle.mu),
+        steps(cycle.mu + cycle.lambda),
+        steps(cycle.lambda)
+    )
 }
 
 pub fn run_6a() -> usize {
@@ -89,4 +171,47 @@ mod tests {
     fn sample_6b() {
         assert_eq!(run_6b_with_input("0 2 7 0"), 4);
     }
+
+    #[test]
+    fn fast_step_matches_slow_step() {
+        for seed in 0..50 {
+            let banks = 1 + seed as usize % 13;
+            let mut fast = parse_vm(&generate_banks_6(banks, 40, seed));
+            let mut slow = fast.clone();
+
+            for _ in 0..100 {
+                fast.step();
+                slow.step_slow();
+                assert_eq!(fast, slow, "Seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn large_banks() {
+        let mut vm = VM {
+            blocks: vec![u64::MAX, 0, 0, 0],
+        };
+        vm.step();
+
+        let lap = u64::MAX / 4;
+        assert_eq!(vm.blocks, vec![lap, lap + 1, lap + 1, lap + 1]);
+
+        let banks = parse_vm(&generate_banks_6(2000, 1 << 40, 7));
+        assert_eq!(banks.blocks.len(), 2000);
+        assert!(banks.blocks.iter().all(|&b| b <= 1 << 40));
+    }
+
+    #[test]
+    fn sample_6_methods() {
+        for method in [Method::Floyd, Method::Brent, Method::Hashing]
+            .iter()
+            .copied()
+        {
+            assert_eq!(
+                cycle_6("0 2 7 0", method),
+                "Tail of 1 step, first repeat after 5 steps, looping every 4 steps"
+            );
+        }
+    }
 }
@@ -120,72 +120,407 @@ fn parse_tree(input: &str) -> Tree {
     leaves.into_iter().map(|(_, v)| v).next().unwrap()
 }
 
-fn run_7a_with_input(input: &str) -> String {
-    parse_tree(input).name
+/// The tower flattened into arrays indexed by program, in pre-order (so the root is 0 and
+/// every parent comes before its children), with each subtree's total weight worked out
+struct Tower {
+    names: Vec<String>,
+    weights: Vec<u64>,
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    depths: Vec<usize>,
+    totals: Vec<u64>,
+    index: HashMap<String, usize>,
 }
 
-pub fn run_7a() -> String {
-    run_7a_with_input(INPUT)
+/// Setting one program's weight to `new_weight` balances the whole tower
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+struct Fix {
+    node: usize,
+    old_weight: u64,
+    new_weight: u64,
 }
 
-fn run_7b_with_input(input: &str) -> u32 {
-    let tree = parse_tree(input);
-
-    // Basically the idea is this; we recursively search
-    // The use of Result is janky but it makes the code SO much shorter because we can use ?
-    // Basically Ok(w) means "everything was fine and this is the total weight of the node"
-    // Err(w) means "something was the wrong weight and this is what it should be"
-    fn dfs(tree: &Tree) -> Result<u32, u32> {
-        let num_children = tree.children.len();
-
-        match num_children {
-            0 => Ok(tree.weight),
-            1 => Ok(tree.weight + dfs(&tree.children[0])?),
-            num_children => {
-                let mut child_weights: Vec<u32> = Vec::with_capacity(num_children);
-                let mut child_weight_set: HashMap<u32, usize> = HashMap::new(); // weight -> count of that weight
-                for child in &tree.children {
-                    let child_weight = dfs(child)?;
-                    child_weights.push(child_weight);
-                    *child_weight_set.entry(child_weight).or_insert(0) += 1;
-                }
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Rebalance {
+    Balanced,
+    Unique(Fix),
+    /// More than one program could be changed, each on its own enough
+    Ambiguous(Vec<Fix>),
+    /// No single weight change balances everything
+    Impossible,
+}
+
+impl Tower {
+    fn new(tree: Tree) -> Tower {
+        let mut tower = Tower {
+            names: Vec::new(),
+            weights: Vec::new(),
+            parents: Vec::new(),
+            children: Vec::new(),
+            depths: Vec::new(),
+            totals: Vec::new(),
+            index: HashMap::new(),
+        };
+
+        let mut stack: Vec<(Tree, Option<usize>)> = vec![(tree, None)];
+
+        while let Some((tree, parent)) = stack.pop() {
+            let node = tower.names.len();
+
+            tower.index.insert(tree.name.clone(), node);
+            tower.names.push(tree.name);
+            tower.weights.push(tree.weight as u64);
+            tower.parents.push(parent);
+            tower.children.push(Vec::new());
+            tower
+                .depths
+                .push(parent.map(|p| tower.depths[p] + 1).unwrap_or(0));
+
+            if let Some(parent) = parent {
+                tower.children[parent].push(node);
+            }
 
-                if child_weight_set.len() == 1 {
-                    Ok(tree.weight + child_weights[0] * (num_children as u32))
-                } else if num_children == 2 {
-                    panic!("Bad input: if there are two children and they have differing weights, the solution is ambiguous");
+            // reversed so they come off the stack, and get numbered, in their original order
+            for child in tree.children.into_iter().rev() {
+                stack.push((child, Some(node)));
+            }
+        }
+
+        // children come after their parents, so going backwards sees every subtree finished
+        tower.totals = tower.weights.clone();
+        for node in (1..tower.len()).rev() {
+            let parent = tower.parents[node].unwrap();
+            tower.totals[parent] += tower.totals[node];
+        }
+
+        tower
+    }
+
+    fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    fn root(&self) -> &str {
+        &self.names[0]
+    }
+
+    fn subtree_weight(&self, name: &str) -> Option<u64> {
+        self.index.get(name).map(|&node| self.totals[node])
+    }
+
+    fn is_balanced(&self, node: usize) -> bool {
+        let children = &self.children[node];
+
+        children
+            .iter()
+            .all(|&child| self.totals[child] == self.totals[children[0]])
+    }
+
+    /// Every program whose children don't all weigh the same, in pre-order
+    fn imbalanced(&self) -> Vec<usize> {
+        (0..self.len())
+            .filter(|&node| !self.is_balanced(node))
+            .collect()
+    }
+
+    fn ancestors(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.parents[node], move |&n| self.parents[n])
+    }
+
+    /// Whether adding `delta` to the subtree weight of `node` leaves all its ancestors balanced
+    fn balanced_after(&self, node: usize, delta: i64) -> bool {
+        let mut changed = node;
+
+        for ancestor in self.ancestors(node) {
+            let total = |child: usize| {
+                if child == changed {
+                    self.totals[child] as i64 + delta
                 } else {
-                    // We assume a good weight exists
-                    let desired_weight = child_weight_set
-                        .iter()
-                        .filter(|(_, v)| **v != 1)
-                        .map(|(k, _)| *k)
-                        .next()
-                        .unwrap();
-
-                    for (child_ind, child_weight) in child_weights.iter().copied().enumerate() {
-                        if child_weight != desired_weight {
-                            let node_weight = tree.children[child_ind].weight;
-                            let desired_weight = node_weight + desired_weight - child_weight;
-                            return Err(desired_weight);
-                        }
-                    }
+                    self.totals[child] as i64
+                }
+            };
 
-                    unreachable!()
+            let children = &self.children[ancestor];
+            if children.iter().any(|&c| total(c) != total(children[0])) {
+                return false;
+            }
+
+            changed = ancestor;
+        }
+
+        true
+    }
+
+    /// Every way to balance the tower by changing a single program's weight.
+    ///
+    /// If all the imbalance is along one path from the root, the deepest imbalanced program
+    /// has to have one child whose subtree weight is off; that child, or anything below it
+    /// in a chain of only-children, can take up the difference. Each candidate is kept if
+    /// the change also balances everything above it.
+    fn fixes(&self) -> Vec<Fix> {
+        let imbalanced = self.imbalanced();
+
+        let deepest = match imbalanced.iter().copied().max_by_key(|&n| self.depths[n]) {
+            Some(deepest) => deepest,
+            None => return Vec::new(),
+        };
+
+        let on_path: Vec<usize> = std::iter::once(deepest)
+            .chain(self.ancestors(deepest))
+            .collect();
+        if imbalanced.iter().any(|n| !on_path.contains(n)) {
+            return Vec::new();
+        }
+
+        let siblings = &self.children[deepest];
+        let mut out = Vec::new();
+
+        for &child in siblings.iter() {
+            let mut others = siblings.iter().filter(|&&s| s != child);
+            let target = self.totals[*others.next().unwrap()];
+            if others.any(|&s| self.totals[s] != target) {
+                continue;
+            }
+
+            let delta = target as i64 - self.totals[child] as i64;
+            if !self.balanced_after(deepest, delta) {
+                continue;
+            }
+
+            let mut node = child;
+            loop {
+                let new_weight = self.weights[node] as i64 + delta;
+                if new_weight >= 0 {
+                    out.push(Fix {
+                        node,
+                        old_weight: self.weights[node],
+                        new_weight: new_weight as u64,
+                    });
+                }
+
+                match self.children[node].as_slice() {
+                    [only] => node = *only,
+                    _ => break,
                 }
             }
         }
+
+        out
     }
 
-    match dfs(&tree) {
-        Ok(_) => {
-            panic!("No unbalanced node detected");
+    fn rebalance(&self) -> Rebalance {
+        if self.imbalanced().is_empty() {
+            return Rebalance::Balanced;
+        }
+
+        let mut fixes = self.fixes();
+
+        match fixes.len() {
+            0 => Rebalance::Impossible,
+            1 => Rebalance::Unique(fixes.pop().unwrap()),
+            _ => Rebalance::Ambiguous(fixes),
         }
-        Err(corrected) => corrected,
     }
 }
 
-pub fn run_7b() -> u32 {
+/// Ways to look at a tower. Program names are alphanumeric (the parser only takes those),
+/// so they go into DOT and JSON without any escaping. DOT IDs are still quoted, though,
+/// since a bare one can't start with a digit or be a keyword like `node`.
+mod render {
+    use super::Tower;
+
+    /// Graphviz source: each program labelled with its own and its subtree's weight, and
+    /// the imbalanced ones filled in red
+    pub(super) fn to_dot(tower: &Tower) -> String {
+        let mut out = String::from("digraph tower {\n    node [shape=box];\n");
+
+        for node in 0..tower.len() {
+            let highlight = if tower.is_balanced(node) {
+                ""
+            } else {
+                ", style=filled, fillcolor=\"#f4a6a6\""
+            };
+
+            out.push_str(&format!(
+                "    \"{}\" [label=\"{}\\nweight {}\\ntotal {}\"{}];\n",
+                tower.names[node],
+                tower.names[node],
+                tower.weights[node],
+                tower.totals[node],
+                highlight
+            ));
+        }
+
+        for node in 0..tower.len() {
+            for &child in tower.children[node].iter() {
+                out.push_str(&format!(
+                    "    \"{}\" -> \"{}\";\n",
+                    tower.names[node], tower.names[child]
+                ));
+            }
+        }
+
+        out.push('}');
+        out
+    }
+
+    /// Nested objects, one per program, with its children in a "children" array
+    pub(super) fn to_json(tower: &Tower) -> String {
+        let mut out = String::new();
+
+        // programs whose children are still being written out, and how many have been
+        let mut stack: Vec<(usize, usize)> = Vec::new();
+        let mut next = Some(0);
+
+        loop {
+            if let Some(node) = next.take() {
+                out.push_str(&format!(
+                    "{{\"name\":\"{}\",\"weight\":{},\"total\":{},\"balanced\":{},\"children\":[",
+                    tower.names[node],
+                    tower.weights[node],
+                    tower.totals[node],
+                    tower.is_balanced(node)
+                ));
+                stack.push((node, 0));
+            }
+
+            let (node, written) = match stack.last_mut() {
+                Some(top) => top,
+                None => break,
+            };
+
+            match tower.children[*node].get(*written) {
+                Some(&child) => {
+                    if *written > 0 {
+                        out.push(',');
+                    }
+                    *written += 1;
+                    next = Some(child);
+                }
+                None => {
+                    out.push_str("]}");
+                    stack.pop();
+                }
+            }
+        }
+
+        out
+    }
+
+    /// An indented tree, one program per line as `name (weight) [total]`, with the
+    /// imbalanced ones marked
+    pub(super) fn to_ascii(tower: &Tower) -> String {
+        let mut lines: Vec<String> = Vec::new();
+
+        // each program with the prefix its own children get, and its line's connector
+        let mut stack: Vec<(usize, String, &str)> = vec![(0, String::new(), "")];
+
+        while let Some((node, prefix, connector)) = stack.pop() {
+            let (indent, child_prefix) = match connector {
+                "" => (String::new(), String::new()),
+                "└── " => (prefix.clone(), prefix + "    "),
+                _ => (prefix.clone(), prefix + "│   "),
+            };
+
+            let mut line = format!(
+                "{}{}{} ({}) [{}]",
+                indent, connector, tower.names[node], tower.weights[node], tower.totals[node]
+            );
+            if !tower.is_balanced(node) {
+                line.push_str(" *imbalanced*");
+            }
+            lines.push(line);
+
+            let children = &tower.children[node];
+            for (i, &child) in children.iter().enumerate().rev() {
+                let connector = if i + 1 == children.len() {
+                    "└── "
+                } else {
+                    "├── "
+                };
+                stack.push((child, child_prefix.clone(), connector));
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// The tower as "dot", "json" or an ASCII "tree"
+pub fn export_7(input: &str, format: &str) -> String {
+    let tower = Tower::new(parse_tree(input));
+
+    match format {
+        "dot" => render::to_dot(&tower),
+        "json" => render::to_json(&tower),
+        "tree" => render::to_ascii(&tower),
+        other => format!("Unrecognized export format {}", other),
+    }
+}
+
+/// The root, every imbalanced program with its children's subtree weights, and what
+/// single weight changes would balance the tower
+pub fn rebalance_7(input: &str) -> String {
+    let tower = Tower::new(parse_tree(input));
+
+    let mut out = format!(
+        "Root: {} (total weight {})\n",
+        tower.root(),
+        tower.subtree_weight(tower.root()).unwrap()
+    );
+
+    for node in tower.imbalanced() {
+        let children: Vec<String> = tower.children[node]
+            .iter()
+            .map(|&child| format!("{} {}", tower.names[child], tower.totals[child]))
+            .collect();
+        out.push_str(&format!(
+            "Imbalanced: {} holds {}\n",
+            tower.names[node],
+            children.join(", ")
+        ));
+    }
+
+    let describe = |fix: &Fix| {
+        format!(
+            "{} {} -> {}",
+            tower.names[fix.node], fix.old_weight, fix.new_weight
+        )
+    };
+
+    match tower.rebalance() {
+        Rebalance::Balanced => out.push_str("Balanced"),
+        Rebalance::Unique(fix) => out.push_str(&format!("Fix: {}", describe(&fix))),
+        Rebalance::Ambiguous(fixes) => {
+            out.push_str("Ambiguous, any one of:");
+            for fix in fixes.iter() {
+                out.push_str(&format!("\n  {}", describe(fix)));
+            }
+        }
+        Rebalance::Impossible => out.push_str("No single weight change balances the tower"),
+    }
+
+    out
+}
+
+fn run_7a_with_input(input: &str) -> String {
+    parse_tree(input).name
+}
+
+pub fn run_7a() -> String {
+    run_7a_with_input(INPUT)
+}
+
+fn run_7b_with_input(input: &str) -> u64 {
+    let tower = Tower::new(parse_tree(input));
+
+    match tower.rebalance() {
+        Rebalance::Unique(fix) => fix.new_weight,
+        other => panic!("Expected exactly one fix, got {:?}", other),
+    }
+}
+
+pub fn run_7b() -> u64 {
     run_7b_with_input(INPUT)
 }
 
@@ -234,4 +569,140 @@ cntj (57)";
 
         assert_eq!(output, 60);
     }
+
+    fn rebalance(input: &str) -> Vec<(String, u64)> {
+        let tower = Tower::new(parse_tree(input));
+        let fixes = match tower.rebalance() {
+            Rebalance::Balanced | Rebalance::Impossible => Vec::new(),
+            Rebalance::Unique(fix) => vec![fix],
+            Rebalance::Ambiguous(fixes) => fixes,
+        };
+
+        fixes
+            .iter()
+            .map(|fix| (tower.names[fix.node].clone(), fix.new_weight))
+            .collect()
+    }
+
+    #[test]
+    fn tower_queries() {
+        let tower = Tower::new(parse_tree(
+            "pbga (66)
+xhth (57)
+ebii (61)
+havc (66)
+ktlj (57)
+fwft (72) -> ktlj, cntj, xhth
+qoyq (66)
+padx (45) -> pbga, havc, qoyq
+tknk (41) -> ugml, padx, fwft
+jptl (61)
+ugml (68) -> gyxo, ebii, jptl
+gyxo (61)
+cntj (57)",
+        ));
+
+        assert_eq!(tower.root(), "tknk");
+        assert_eq!(tower.subtree_weight("ugml"), Some(251));
+        assert_eq!(tower.subtree_weight("padx"), Some(243));
+        assert_eq!(tower.subtree_weight("tknk"), Some(41 + 251 + 243 + 243));
+        assert_eq!(tower.subtree_weight("nobody"), None);
+
+        let imbalanced: Vec<&str> = tower
+            .imbalanced()
+            .iter()
+            .map(|&n| tower.names[n].as_str())
+            .collect();
+        assert_eq!(imbalanced, vec!["tknk"]);
+    }
+
+    #[test]
+    fn tower_exports() {
+        let tower = Tower::new(parse_tree(
+            "r (1) -> x, y, z\nx (2) -> p, q\np (2)\nq (3)\ny (8)\nz (8)",
+        ));
+
+        assert_eq!(
+            render::to_ascii(&tower),
+            "r (1) [24] *imbalanced*
+├── y (8) [8]
+├── z (8) [8]
+└── x (2) [7] *imbalanced*
+    ├── p (2) [2]
+    └── q (3) [3]"
+        );
+
+        assert_eq!(
+            render::to_json(&tower),
+            concat!(
+                r#"{"name":"r","weight":1,"total":24,"balanced":false,"children":["#,
+                r#"{"name":"y","weight":8,"total":8,"balanced":true,"children":[]},"#,
+                r#"{"name":"z","weight":8,"total":8,"balanced":true,"children":[]},"#,
+                r#"{"name":"x","weight":2,"total":7,"balanced":false,"children":["#,
+                r#"{"name":"p","weight":2,"total":2,"balanced":true,"children":[]},"#,
+                r#"{"name":"q","weight":3,"total":3,"balanced":true,"children":[]}]}]}"#
+            )
+        );
+
+        let dot = render::to_dot(&tower);
+        assert!(dot.starts_with("digraph tower {"));
+        assert!(dot.contains(
+            "    \"x\" [label=\"x\\nweight 2\\ntotal 7\", style=filled, fillcolor=\"#f4a6a6\"];"
+        ));
+        assert!(dot.contains("    \"y\" [label=\"y\\nweight 8\\ntotal 8\"];"));
+        assert!(dot.contains("    \"x\" -> \"q\";"));
+        assert_eq!(dot.matches("->").count(), tower.len() - 1);
+
+        // names that wouldn't do as bare DOT IDs
+        let dot = render::to_dot(&Tower::new(parse_tree(
+            "node (1) -> 1a, graph\n1a (2)\ngraph (2)",
+        )));
+        assert!(dot.contains("    \"node\" [label=\"node\\nweight 1\\ntotal 5\"];"));
+        assert!(dot.contains("    \"node\" -> \"1a\";"));
+        assert!(dot.contains("    \"node\" -> \"graph\";"));
+    }
+
+    #[test]
+    fn tower_rebalancing() {
+        assert_eq!(
+            Tower::new(parse_tree("a (1) -> b, c\nb (2)\nc (2)")).rebalance(),
+            Rebalance::Balanced
+        );
+
+        // two children at the root could go either way
+        assert_eq!(
+            rebalance("a (1) -> b, c\nb (2)\nc (3)"),
+            vec![("b".to_string(), 3), ("c".to_string(), 2)]
+        );
+
+        // but lower down, the level above decides
+        assert_eq!(
+            rebalance("r (1) -> x, y, z\nx (2) -> p, q\np (2)\nq (3)\ny (8)\nz (8)"),
+            vec![("p".to_string(), 3)]
+        );
+
+        // an only child can make up the difference as well as its parent
+        assert_eq!(
+            rebalance("a (1) -> b, c, d\nb (5)\nc (5)\nd (1) -> e\ne (3)"),
+            vec![("d".to_string(), 2), ("e".to_string(), 4)]
+        );
+
+        // separate imbalances in separate branches need more than one change
+        assert_eq!(
+            Tower::new(parse_tree(
+                "r (1) -> x, y, z
+x (1) -> a, b, c
+a (1)
+b (1)
+c (2)
+y (1) -> d, e, f
+d (1)
+e (1)
+f (2)
+z (6)"
+            ))
+            .rebalance(),
+            Rebalance::Impossible
+        );
+    }
 }
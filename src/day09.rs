@@ -1,157 +1,880 @@
 const INPUT: &str = include_str!("input/9.txt");
 
+/// A push parser for the stream format: bytes go in as they arrive, in chunks of any size,
+/// and it keeps only a flat state machine (plus, if asked for, the group tree being built)
+/// so deeply nested streams can't blow the stack. Malformed input is reported with its
+/// byte position and skipped over, so one bad byte doesn't lose the rest of the stream.
 mod parser {
-    use nom::multi::fold_many0;
-    use nom::{
-        branch::alt,
-        character::complete::{anychar, char, none_of},
-        multi::separated_list0,
-        sequence::tuple,
-        IResult, Parser,
-    };
-
-    #[derive(Clone, Eq, PartialEq, Debug)]
+    #[derive(Clone, Eq, PartialEq, Debug, Default)]
     pub(crate) struct Group {
-        pub(crate) garbage: Vec<Garbage>,
-        pub(crate) children: Vec<Group>,
+        /// Groups and garbage in the order they appeared, so the stream can be rebuilt
+        pub(crate) items: Vec<Item>,
     }
 
-    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
-    pub(crate) struct Garbage {
-        pub contents: usize,
-    }
-
-    fn parse_garbage(input: &str) -> IResult<&str, Garbage> {
-        let mut parser = tuple((
-            // Start with open angle
-            char('<'),
-            // Any sequence of stuff that doesn't end the garbage block
-            // Note we are capturing the quantity of garbage
-            fold_many0(
-                alt((
-                    // escape inside garbage (contents skipped)
-                    tuple((char('!'), anychar)).map(|_| 0),
-                    // or just whatever isn't a close angle (contents counted)
-                    none_of(">").map(|_| 1),
-                )),
-                // add em up
-                0,
-                |acc, item| acc + item,
-            ),
-            // End with close angle
-            char('>'),
-        ));
-
-        let (input, (_, contents, _)) = parser(input)?;
-
-        std::mem::drop(parser);
-
-        Ok((input, Garbage { contents }))
-    }
-
-    enum GroupOrGarbage {
+    /// The derived drop would recurse once per level of nesting, so flatten the tree first
+    impl Drop for Group {
+        fn drop(&mut self) {
+            let mut pending = std::mem::take(&mut self.items);
+
+            while let Some(item) = pending.pop() {
+                if let Item::Group(mut group) = item {
+                    pending.append(&mut group.items);
+                }
+            }
+        }
+    }
+
+    #[derive(Clone, Eq, PartialEq, Debug)]
+    pub(crate) enum Item {
         Group(Group),
         Garbage(Garbage),
     }
 
-    fn parse_group_or_garbage(input: &str) -> IResult<&str, GroupOrGarbage> {
-        alt((
-            parse_garbage.map(|g| GroupOrGarbage::Garbage(g)),
-            parse_group.map(|g| GroupOrGarbage::Group(g)),
-        ))(input)
+    #[derive(Clone, Eq, PartialEq, Debug)]
+    pub(crate) struct Garbage {
+        /// Everything between the angle brackets, `!`s and all
+        pub(crate) raw: String,
+    }
+
+    impl Garbage {
+        /// What's left once every `!` and the character it cancels are taken out
+        pub(crate) fn unescaped(&self) -> String {
+            let mut out = String::new();
+            let mut chars = self.raw.chars();
+
+            while let Some(c) = chars.next() {
+                if c == '!' {
+                    chars.next();
+                } else {
+                    out.push(c);
+                }
+            }
+
+            out
+        }
+    }
+
+    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+    pub(crate) enum ErrorKind {
+        UnexpectedByte(u8),
+        /// Two items in a group with no comma between them
+        MissingComma,
+        /// A comma somewhere other than between two items
+        UnexpectedComma,
+        /// A comma right before the close of a group
+        TrailingComma,
+        UnmatchedClose,
+        GarbageOutsideGroup,
+        /// Anything but whitespace after the outermost group has closed
+        TrailingInput,
+        UnterminatedGarbage,
+        /// The stream ended with this many groups still open
+        UnclosedGroups(usize),
+        Empty,
+    }
+
+    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+    pub(crate) struct StreamError {
+        /// Byte offset into the stream; for unterminated garbage this is where it was opened
+        pub(crate) position: u64,
+        pub(crate) kind: ErrorKind,
+    }
+
+    impl std::fmt::Display for StreamError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Byte {}: ", self.position)?;
+
+            match self.kind {
+                ErrorKind::UnexpectedByte(b) => write!(f, "unexpected {:?}", b as char),
+                ErrorKind::MissingComma => write!(f, "missing comma between items"),
+                ErrorKind::UnexpectedComma => write!(f, "unexpected comma"),
+                ErrorKind::TrailingComma => write!(f, "comma before end of group"),
+                ErrorKind::UnmatchedClose => write!(f, "'}}' without a matching '{{'"),
+                ErrorKind::GarbageOutsideGroup => write!(f, "garbage outside any group"),
+                ErrorKind::TrailingInput => write!(f, "input after the outermost group"),
+                ErrorKind::UnterminatedGarbage => write!(f, "garbage is never closed"),
+                ErrorKind::UnclosedGroups(n) => write!(f, "stream ended with {} open groups", n),
+                ErrorKind::Empty => write!(f, "stream has no groups"),
+            }
+        }
+    }
+
+    #[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+    pub(crate) struct Stats {
+        pub(crate) groups: u64,
+        /// Sum of every group's depth, counting the outermost as 1
+        pub(crate) score: u64,
+        pub(crate) max_depth: usize,
+        /// Characters inside garbage, not counting the brackets or anything cancelled
+        pub(crate) garbage: u64,
+        /// Characters cancelled with `!`, not counting the `!`s themselves
+        pub(crate) cancelled: u64,
+    }
+
+    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+    enum Expect {
+        /// Nothing seen yet
+        Open,
+        ItemOrClose,
+        CommaOrClose,
+        /// Just after a comma
+        Item,
+        /// The outermost group has closed
+        End,
     }
 
-    fn parse_group(input: &str) -> IResult<&str, Group> {
-        let out = tuple((
-            char('{'),
-            separated_list0(char(','), parse_group_or_garbage),
-            char('}'),
-        ))(input);
+    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+    enum State {
+        Groups(Expect),
+        /// Inside garbage, which started at the given position
+        Garbage {
+            opened_at: u64,
+            cancel: bool,
+        },
+    }
+
+    pub(crate) struct StreamParser {
+        state: State,
+        position: u64,
+        depth: usize,
+        stats: Stats,
+        errors: Vec<StreamError>,
+        /// Groups still open, outermost first, when building a tree
+        tree: Option<Vec<Group>>,
+        garbage_raw: Vec<u8>,
+        root: Option<Group>,
+    }
+
+    pub(crate) struct Parsed {
+        pub(crate) stats: Stats,
+        pub(crate) errors: Vec<StreamError>,
+        /// The outermost group, if a tree was asked for and there was one
+        pub(crate) root: Option<Group>,
+    }
+
+    impl StreamParser {
+        pub(crate) fn new() -> StreamParser {
+            StreamParser {
+                state: State::Groups(Expect::Open),
+                position: 0,
+                depth: 0,
+                stats: Stats::default(),
+                errors: Vec::new(),
+                tree: None,
+                garbage_raw: Vec::new(),
+                root: None,
+            }
+        }
+
+        /// Also builds the `Group` tree, which costs memory proportional to the stream
+        pub(crate) fn with_tree() -> StreamParser {
+            StreamParser {
+                tree: Some(Vec::new()),
+                ..StreamParser::new()
+            }
+        }
+
+        fn error(&mut self, kind: ErrorKind) {
+            self.errors.push(StreamError {
+                position: self.position,
+                kind,
+            });
+        }
+
+        fn expect(&self) -> Expect {
+            match self.state {
+                State::Groups(expect) => expect,
+                State::Garbage { .. } => unreachable!("Not between items"),
+            }
+        }
+
+        /// A group or garbage has just finished
+        fn item_done(&mut self) {
+            self.state = State::Groups(if self.depth == 0 {
+                Expect::End
+            } else {
+                Expect::CommaOrClose
+            });
+        }
+
+        /// Checks that an item may start here, complaining (but carrying on) if not
+        fn item_start(&mut self) {
+            match self.expect() {
+                Expect::CommaOrClose => self.error(ErrorKind::MissingComma),
+                Expect::End => self.error(ErrorKind::TrailingInput),
+                Expect::Open | Expect::ItemOrClose | Expect::Item => {}
+            }
+        }
+
+        fn open_group(&mut self) {
+            self.item_start();
 
-        out.map(|(input, output)| {
-            let (_, subgroups, _) = output;
-            let mut children = vec![];
-            let mut garbage = vec![];
+            self.depth += 1;
+            self.stats.groups += 1;
+            self.stats.score += self.depth as u64;
+            self.stats.max_depth = self.stats.max_depth.max(self.depth);
 
-            for child in subgroups {
-                match child {
-                    GroupOrGarbage::Group(g) => children.push(g),
-                    GroupOrGarbage::Garbage(g) => garbage.push(g),
+            if let Some(stack) = self.tree.as_mut() {
+                stack.push(Group::default());
+            }
+
+            self.state = State::Groups(Expect::ItemOrClose);
+        }
+
+        fn close_group(&mut self) {
+            if let Some(stack) = self.tree.as_mut() {
+                let group = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(parent) => parent.items.push(Item::Group(group)),
+                    // only the first outermost group is kept
+                    None if self.root.is_none() => self.root = Some(group),
+                    None => {}
+                }
+            }
+
+            self.depth -= 1;
+            self.item_done();
+        }
+
+        fn close_garbage(&mut self) {
+            let raw = std::mem::take(&mut self.garbage_raw);
+            if let Some(parent) = self.tree.as_mut().and_then(|stack| stack.last_mut()) {
+                let raw = String::from_utf8(raw)
+                    .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+                parent.items.push(Item::Garbage(Garbage { raw }));
+            }
+
+            if self.depth == 0 {
+                // it was already reported; carry on as though it wasn't there
+                self.state = State::Groups(if self.stats.groups == 0 {
+                    Expect::Open
+                } else {
+                    Expect::End
+                });
+            } else {
+                self.item_done();
+            }
+        }
+
+        fn push_byte(&mut self, b: u8) {
+            if self.tree.is_some() {
+                if let State::Garbage { cancel, .. } = self.state {
+                    if cancel || b != b'>' {
+                        self.garbage_raw.push(b);
+                    }
+                }
+            }
+
+            match self.state {
+                State::Garbage {
+                    opened_at,
+                    cancel: true,
+                } => {
+                    self.stats.cancelled += 1;
+                    self.state = State::Garbage {
+                        opened_at,
+                        cancel: false,
+                    };
+                }
+                State::Garbage { opened_at, .. } => match b {
+                    b'!' => {
+                        self.state = State::Garbage {
+                            opened_at,
+                            cancel: true,
+                        }
+                    }
+                    b'>' => self.close_garbage(),
+                    _ => self.stats.garbage += 1,
+                },
+                State::Groups(expect) => match b {
+                    b'{' => self.open_group(),
+                    b'}' if self.depth == 0 => self.error(ErrorKind::UnmatchedClose),
+                    b'}' => {
+                        if expect == Expect::Item {
+                            self.error(ErrorKind::TrailingComma);
+                        }
+                        self.close_group();
+                    }
+                    b'<' => {
+                        if self.depth == 0 {
+                            self.error(ErrorKind::GarbageOutsideGroup);
+                        } else {
+                            self.item_start();
+                        }
+
+                        self.state = State::Garbage {
+                            opened_at: self.position,
+                            cancel: false,
+                        };
+                    }
+                    b',' if expect == Expect::CommaOrClose => {
+                        self.state = State::Groups(Expect::Item)
+                    }
+                    b',' => self.error(ErrorKind::UnexpectedComma),
+                    b if b.is_ascii_whitespace() => {}
+                    _ if expect == Expect::End => self.error(ErrorKind::TrailingInput),
+                    b => self.error(ErrorKind::UnexpectedByte(b)),
+                },
+            }
+
+            self.position += 1;
+        }
+
+        pub(crate) fn push(&mut self, chunk: &[u8]) {
+            for b in chunk.iter().copied() {
+                self.push_byte(b);
+            }
+        }
+
+        /// Ends the stream, reporting anything left open
+        pub(crate) fn finish(mut self) -> Parsed {
+            if let State::Garbage { opened_at, .. } = self.state {
+                self.errors.push(StreamError {
+                    position: opened_at,
+                    kind: ErrorKind::UnterminatedGarbage,
+                });
+                self.close_garbage();
+            }
+
+            if self.depth > 0 {
+                self.error(ErrorKind::UnclosedGroups(self.depth));
+                while self.depth > 0 {
+                    self.close_group();
                 }
             }
 
-            (input, Group { children, garbage })
-        })
+            if self.stats.groups == 0 {
+                self.error(ErrorKind::Empty);
+            }
+
+            Parsed {
+                stats: self.stats,
+                errors: self.errors,
+                root: self.root,
+            }
+        }
     }
 
-    pub(crate) fn parse(input: &str) -> Group {
-        let (leftover, group) = parse_group(input).unwrap();
-        if !leftover.is_empty() {
-            panic!("Did not parse entire input; leftover: '{}'", leftover);
+    /// The whole stream as a tree of groups, or every problem with it
+    pub(crate) fn parse(input: &str) -> Result<Group, Vec<StreamError>> {
+        let mut parser = StreamParser::with_tree();
+        parser.push(input.as_bytes());
+        let parsed = parser.finish();
+
+        match parsed.root {
+            Some(root) if parsed.errors.is_empty() => Ok(root),
+            _ => Err(parsed.errors),
         }
+    }
+
+    /// Just the totals, without keeping anything but the parser state
+    pub(crate) fn stats(input: &str) -> Result<Stats, Vec<StreamError>> {
+        let mut parser = StreamParser::new();
+        parser.push(input.as_bytes());
+        let parsed = parser.finish();
 
-        group
+        if parsed.errors.is_empty() {
+            Ok(parsed.stats)
+        } else {
+            Err(parsed.errors)
+        }
     }
 
     #[cfg(test)]
     mod tests {
         use super::*;
 
+        fn errors(input: &str) -> Vec<(u64, ErrorKind)> {
+            let mut parser = StreamParser::new();
+            parser.push(input.as_bytes());
+            parser
+                .finish()
+                .errors
+                .iter()
+                .map(|e| (e.position, e.kind))
+                .collect()
+        }
+
         #[test]
         fn test_parse_garbage() {
-            assert_eq!(parse_garbage("<!!>"), Ok(("", Garbage { contents: 0 })));
-            assert_eq!(parse_garbage("<a>"), Ok(("", Garbage { contents: 1 })));
+            assert_eq!(stats("{<!!>}").unwrap().garbage, 0);
+            assert_eq!(stats("{<!!>}").unwrap().cancelled, 1);
+            assert_eq!(stats("{<a>}").unwrap().garbage, 1);
         }
 
         #[test]
         fn test_parse_list() {
             assert_eq!(
-                parse_group("{<>,<>}"),
-                Ok((
-                    "",
-                    Group {
-                        garbage: vec![Garbage { contents: 0 }, Garbage { contents: 0 }],
-                        children: vec![]
-                    }
-                ))
+                stats("{<>,<>}"),
+                Ok(Stats {
+                    groups: 1,
+                    score: 1,
+                    max_depth: 1,
+                    garbage: 0,
+                    cancelled: 0,
+                })
+            );
+        }
+
+        #[test]
+        fn test_parse_tree() {
+            let garbage = |raw: &str| {
+                Item::Garbage(Garbage {
+                    raw: raw.to_string(),
+                })
+            };
+
+            assert_eq!(
+                parse("{<a!>b>,{},<!!>}"),
+                Ok(Group {
+                    items: vec![
+                        garbage("a!>b"),
+                        Item::Group(Group::default()),
+                        garbage("!!")
+                    ]
+                })
+            );
+            assert_eq!(
+                Garbage {
+                    raw: "a!>b!!c!".to_string()
+                }
+                .unescaped(),
+                "abc"
+            );
+            assert!(parse("{<a>").is_err());
+        }
+
+        #[test]
+        fn test_chunks_and_depth() {
+            let input = "{{<a!>b>,{<!!c>}},{}}\n";
+            let whole = stats(input).unwrap();
+
+            assert_eq!(
+                whole,
+                Stats {
+                    groups: 4,
+                    score: 1 + 2 + 3 + 2,
+                    max_depth: 3,
+                    garbage: 3,
+                    cancelled: 2,
+                }
+            );
+
+            // split everywhere, including between a '!' and what it cancels
+            for size in 1..input.len() {
+                let mut parser = StreamParser::new();
+                for chunk in input.as_bytes().chunks(size) {
+                    parser.push(chunk);
+                }
+                let parsed = parser.finish();
+                assert_eq!(parsed.stats, whole, "Chunk size {}", size);
+                assert!(parsed.errors.is_empty());
+            }
+
+            // far deeper than any recursive parser could manage
+            let deep = "{".repeat(1_000_000) + &"}".repeat(1_000_000);
+            let deep_stats = stats(&deep).unwrap();
+            assert_eq!(deep_stats.max_depth, 1_000_000);
+            assert_eq!(deep_stats.score, 1_000_000 * 1_000_001 / 2);
+        }
+
+        #[test]
+        fn test_error_recovery() {
+            assert_eq!(errors("{{}{}}"), vec![(3, ErrorKind::MissingComma)]);
+            assert_eq!(
+                errors("{,{},}x"),
+                vec![
+                    (1, ErrorKind::UnexpectedComma),
+                    (5, ErrorKind::TrailingComma),
+                    (6, ErrorKind::TrailingInput),
+                ]
+            );
+            assert_eq!(
+                errors("}<a>{a}"),
+                vec![
+                    (0, ErrorKind::UnmatchedClose),
+                    (1, ErrorKind::GarbageOutsideGroup),
+                    (5, ErrorKind::UnexpectedByte(b'a')),
+                ]
+            );
+            assert_eq!(
+                errors("{{<ab!>"),
+                vec![
+                    (2, ErrorKind::UnterminatedGarbage),
+                    (7, ErrorKind::UnclosedGroups(2)),
+                ]
             );
+            assert_eq!(errors(" \n"), vec![(2, ErrorKind::Empty)]);
+
+            // the groups either side of a mistake are still counted
+            let mut parser = StreamParser::new();
+            parser.push(b"{{<x>}{?},{}");
+            let parsed = parser.finish();
+            assert_eq!(parsed.stats.groups, 4);
+            assert_eq!(parsed.stats.score, 1 + 2 + 2 + 2);
+            assert_eq!(parsed.stats.garbage, 1);
+
+            let mut parser = StreamParser::with_tree();
+            parser.push(b"{{<x>}{?},{}");
+            let root = parser.finish().root.unwrap();
+            assert_eq!(root.items.len(), 3);
         }
     }
 }
 
-use parser::{parse, Group};
+/// Turning a group tree back into text: the stream format itself, an indented version of
+/// it, or JSON. All of them walk the tree with an explicit stack rather than recursing.
+mod transform {
+    use super::parser::{Garbage, Group, Item};
 
-fn run_9a_with_input(input: &str) -> i32 {
-    let top = parse(input);
+    /// What to do with garbage on the way out
+    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+    pub(crate) enum GarbageMode {
+        /// Exactly as it came in
+        Keep,
+        /// With cancelled characters (and the `!`s cancelling them) removed
+        Unescape,
+        /// Left out entirely, along with the commas around it
+        Strip,
+    }
 
-    fn dfs(gp: &Group, depth: i32) -> i32 {
-        depth
-            + gp.children
-                .iter()
-                .map(|child| dfs(child, depth + 1))
-                .sum::<i32>()
+    impl std::str::FromStr for GarbageMode {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<GarbageMode, String> {
+            match s {
+                "keep" => Ok(GarbageMode::Keep),
+                "unescape" => Ok(GarbageMode::Unescape),
+                "strip" => Ok(GarbageMode::Strip),
+                other => Err(format!("Unrecognized garbage mode {}", other)),
+            }
+        }
+    }
+
+    impl GarbageMode {
+        fn contents(self, garbage: &Garbage) -> String {
+            match self {
+                GarbageMode::Unescape => garbage.unescaped(),
+                GarbageMode::Keep | GarbageMode::Strip => garbage.raw.clone(),
+            }
+        }
+    }
+
+    /// Depth counts the outermost group as 0; `empty` means nothing will be visited
+    /// between a group's open and close
+    enum Event<'a> {
+        Open { depth: usize, empty: bool },
+        Close { depth: usize, empty: bool },
+        Garbage { depth: usize, garbage: &'a Garbage },
+        Comma,
+    }
+
+    fn walk<'a, F: FnMut(Event<'a>)>(root: &'a Group, mode: GarbageMode, mut visit: F) {
+        let visible = |item: &Item| match item {
+            Item::Group(_) => true,
+            Item::Garbage(_) => mode != GarbageMode::Strip,
+        };
+        let is_empty = |group: &Group| !group.items.iter().any(visible);
+
+        visit(Event::Open {
+            depth: 0,
+            empty: is_empty(root),
+        });
+
+        // items of each open group, the next one to visit, and whether any have been yet
+        let mut stack: Vec<(&'a [Item], usize, bool)> = vec![(&root.items, 0, false)];
+
+        while let Some((items, next, started)) = stack.last_mut() {
+            let items: &'a [Item] = items;
+
+            while *next < items.len() && !visible(&items[*next]) {
+                *next += 1;
+            }
+
+            if *next == items.len() {
+                let empty = !*started;
+                stack.pop();
+                visit(Event::Close {
+                    depth: stack.len(),
+                    empty,
+                });
+                continue;
+            }
+
+            let item = &items[*next];
+            *next += 1;
+
+            if *started {
+                visit(Event::Comma);
+            }
+            *started = true;
+
+            let depth = stack.len();
+            match item {
+                Item::Garbage(garbage) => visit(Event::Garbage { depth, garbage }),
+                Item::Group(group) => {
+                    visit(Event::Open {
+                        depth,
+                        empty: is_empty(group),
+                    });
+                    stack.push((&group.items, 0, false));
+                }
+            }
+        }
+    }
+
+    /// The stream format with no whitespace outside garbage; with `GarbageMode::Keep`
+    /// this parses back to the same tree
+    pub(crate) fn to_stream(root: &Group, mode: GarbageMode) -> String {
+        let mut out = String::new();
+
+        walk(root, mode, |event| match event {
+            Event::Open { .. } => out.push('{'),
+            Event::Close { .. } => out.push('}'),
+            Event::Comma => out.push(','),
+            Event::Garbage { garbage, .. } => {
+                out.push('<');
+                out.push_str(&mode.contents(garbage));
+                out.push('>');
+            }
+        });
+
+        out
+    }
+
+    /// The stream format with every item on its own line, indented by its depth
+    pub(crate) fn to_pretty(root: &Group, mode: GarbageMode) -> String {
+        let mut out = String::new();
+        let indent = |depth: usize| "  ".repeat(depth);
+
+        walk(root, mode, |event| match event {
+            Event::Open { depth, empty: true } => {
+                out.push_str(&indent(depth));
+                out.push_str("{}");
+            }
+            Event::Open { depth, .. } => {
+                out.push_str(&indent(depth));
+                out.push_str("{\n");
+            }
+            Event::Close { empty: true, .. } => {}
+            Event::Close { depth, .. } => {
+                out.push('\n');
+                out.push_str(&indent(depth));
+                out.push('}');
+            }
+            Event::Comma => out.push_str(",\n"),
+            Event::Garbage { depth, garbage } => {
+                out.push_str(&indent(depth));
+                out.push('<');
+                out.push_str(&mode.contents(garbage));
+                out.push('>');
+            }
+        });
+
+        out
+    }
+
+    fn json_string(s: &str) -> String {
+        let mut out = String::from("\"");
+
+        for c in s.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
+            }
+        }
+
+        out.push('"');
+        out
+    }
+
+    /// Groups as nested JSON arrays, with garbage (unless stripped) as strings inside them
+    pub(crate) fn to_json(root: &Group, mode: GarbageMode) -> String {
+        let mut out = String::new();
+
+        walk(root, mode, |event| match event {
+            Event::Open { .. } => out.push('['),
+            Event::Close { .. } => out.push(']'),
+            Event::Comma => out.push(','),
+            Event::Garbage { garbage, .. } => out.push_str(&json_string(&mode.contents(garbage))),
+        });
+
+        out
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::super::parser::parse;
+        use super::*;
+
+        const SAMPLE: &str = "{{<a!>b>,{<!!c>}},<x\"y>,{}}";
+
+        #[test]
+        fn test_round_trip() {
+            let samples = [
+                "{}",
+                "{{{}}}",
+                "{<>,<{o\"i!a,<{i>,{<!!!>>}}",
+                "{{<!>},{<!>},{<!>},{<a>}}",
+                "{<{o\"i!a,<{i<a>}",
+                SAMPLE,
+            ];
+
+            for input in samples.iter() {
+                let tree = parse(input).unwrap();
+                assert_eq!(&to_stream(&tree, GarbageMode::Keep), input);
+
+                // whatever comes out is a valid stream for the same groups
+                for mode in [GarbageMode::Keep, GarbageMode::Unescape, GarbageMode::Strip]
+                    .iter()
+                    .copied()
+                {
+                    let out = to_stream(&tree, mode);
+                    let again = parse(&out).unwrap();
+                    assert_eq!(to_stream(&again, mode), out, "{:?} {}", mode, input);
+                    assert_eq!(
+                        to_stream(&again, GarbageMode::Strip),
+                        to_stream(&tree, GarbageMode::Strip)
+                    );
+                }
+            }
+
+            // whitespace between items isn't kept
+            let spaced = parse("{ {<a b>} ,\n{} }\n").unwrap();
+            assert_eq!(to_stream(&spaced, GarbageMode::Keep), "{{<a b>},{}}");
+        }
+
+        #[test]
+        fn test_transforms() {
+            let tree = parse(SAMPLE).unwrap();
+
+            assert_eq!(
+                to_stream(&tree, GarbageMode::Unescape),
+                "{{<ab>,{<c>}},<x\"y>,{}}"
+            );
+            assert_eq!(to_stream(&tree, GarbageMode::Strip), "{{{}},{}}");
+
+            assert_eq!(
+                to_pretty(&tree, GarbageMode::Keep),
+                "{
+  {
+    <a!>b>,
+    {
+      <!!c>
+    }
+  },
+  <x\"y>,
+  {}
+}"
+            );
+            assert_eq!(
+                to_pretty(&tree, GarbageMode::Strip),
+                "{
+  {
+    {}
+  },
+  {}
+}"
+            );
+
+            assert_eq!(
+                to_json(&tree, GarbageMode::Unescape),
+                r#"[["ab",["c"]],"x\"y",[]]"#
+            );
+            assert_eq!(to_json(&tree, GarbageMode::Strip), "[[[]],[]]");
+
+            let deep = "{".repeat(100_000) + &"}".repeat(100_000);
+            assert_eq!(to_stream(&parse(&deep).unwrap(), GarbageMode::Keep), deep);
+        }
     }
+}
+
+use parser::StreamParser;
+use transform::GarbageMode;
 
-    dfs(&top, 1)
+fn run_9a_with_input(input: &str) -> u64 {
+    parser::stats(input).unwrap().score
 }
 
-pub fn run_9a() -> i32 {
+pub fn run_9a() -> u64 {
     run_9a_with_input(INPUT)
 }
 
-fn run_9b_with_input(input: &str) -> usize {
-    let top = parse(input);
+fn run_9b_with_input(input: &str) -> u64 {
+    parser::stats(input).unwrap().garbage
+}
 
-    fn dfs(gp: &Group) -> usize {
-        gp.garbage.iter().map(|g| g.contents).sum::<usize>()
-            + gp.children.iter().map(dfs).sum::<usize>()
-    }
+pub fn run_9b() -> u64 {
+    run_9b_with_input(INPUT)
+}
+
+/// Rewrites a stream as "stream", "pretty" or "json", keeping, unescaping or stripping
+/// the garbage
+pub fn transform_9(input: &str, format: &str, garbage: &str) -> String {
+    let mode: GarbageMode = match garbage.parse() {
+        Ok(mode) => mode,
+        Err(e) => return e,
+    };
 
-    dfs(&top)
+    let tree = match parser::parse(input) {
+        Ok(tree) => tree,
+        Err(errors) => {
+            return errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join("\n")
+        }
+    };
+
+    match format {
+        "stream" => transform::to_stream(&tree, mode),
+        "pretty" => transform::to_pretty(&tree, mode),
+        "json" => transform::to_json(&tree, mode),
+        other => format!("Unrecognized output format {}", other),
+    }
 }
 
-pub fn run_9b() -> usize {
-    run_9b_with_input(INPUT)
+/// Reads a stream file a chunk at a time, reporting its totals and anything malformed
+pub fn stream_9(path: &str) -> String {
+    use std::io::Read;
+
+    let mut file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) => return format!("Could not open '{}': {}", path, e),
+    };
+
+    let mut parser = StreamParser::new();
+    let mut buffer = [0_u8; 1 << 16];
+
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => parser.push(&buffer[..read]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return format!("Could not read '{}': {}", path, e),
+        }
+    }
+
+    let parsed = parser.finish();
+    let stats = parsed.stats;
+
+    let mut out = String::new();
+    for error in parsed.errors.iter() {
+        out.push_str(&format!("{}\n", error));
+    }
+
+    out.push_str(&format!(
+        "Groups: {}, score: {}, deepest: {}\nGarbage: {} characters, {} cancelled",
+        stats.groups, stats.score, stats.max_depth, stats.garbage, stats.cancelled
+    ));
+
+    out
 }
 
 #[cfg(test)]
@@ -160,7 +883,7 @@ mod tests {
 
     #[test]
     fn sample_9a() {
-        let samples: Vec<(&str, i32)> = vec![
+        let samples: Vec<(&str, u64)> = vec![
             ("{}", 1),
             ("{{{}}}", 6),
             ("{{},{}}", 5),
@@ -179,7 +902,7 @@ mod tests {
 
     #[test]
     fn sample_9b() {
-        let samples: Vec<(&str, usize)> = vec![
+        let samples: Vec<(&str, u64)> = vec![
             ("{<>}", 0),
             ("{{},{<random characters>}}", 17),
             ("{<<<<>}", 3),
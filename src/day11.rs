@@ -1,151 +1,419 @@
+use std::collections::HashMap;
+
+use crate::hex::{Axial, Axis, Cube, Dir};
+
 const INPUT: &str = include_str!("input/11.txt");
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
-enum Dir {
-    NE,
-    SE,
-    SW,
-    NW,
-    N,
-    S,
+fn parse_11a(input: &str) -> impl Iterator<Item = Dir> + '_ {
+    input
+        .trim()
+        .split(',')
+        .map(|token| token.parse().unwrap_or_else(|e: String| panic!("{}", e)))
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
-struct Totals {
-    ne: i32,
-    n: i32,
-    se: i32,
+fn run_11a_with_input(input: &str) -> i64 {
+    parse_11a(input)
+        .fold(Cube::ORIGIN, |pos, dir| pos.neighbor(dir))
+        .length()
 }
 
-impl Totals {
-    fn and_dir(&mut self, dir: Dir) {
-        match dir {
-            Dir::NE => self.ne += 1,
-            Dir::N => self.n += 1,
-            Dir::SE => self.se += 1,
+pub fn run_11a() -> i64 {
+    run_11a_with_input(INPUT)
+}
+
+fn run_11b_with_input(input: &str) -> i64 {
+    parse_11a(input)
+        .scan(Cube::ORIGIN, |pos, dir| {
+            *pos = pos.neighbor(dir);
+            Some(pos.length())
+        })
+        .max()
+        .unwrap_or(0)
+}
 
-            Dir::SW => self.ne -= 1,
-            Dir::S => self.n -= 1,
-            Dir::NW => self.se -= 1,
+pub fn run_11b() -> i64 {
+    run_11b_with_input(INPUT)
+}
+
+/// Every cell the child process stood in, in order, starting from the origin
+struct Walk {
+    cells: Vec<Cube>,
+}
+
+impl Walk {
+    fn new<I: IntoIterator<Item = Dir>>(dirs: I) -> Walk {
+        let mut cells = vec![Cube::ORIGIN];
+        for dir in dirs {
+            let next = cells[cells.len() - 1].neighbor(dir);
+            cells.push(next);
         }
+        Walk { cells }
     }
 
-    fn distance_from_origin(mut self) -> i32 {
-        loop {
-            let mut changed = false;
-
-            // Basically you can replace any inefficient path with a more efficient path
-            // Actual cancellations (NE+SW, SE+NW, E+W) have already been cancelled out and
-            // replaced by signed direction counts.
-            //      N
-            //  NW      NE
-            //  SW      SE
-            //      S
-            // Remaining inefficient paths (note SW is -NE and so on):
-
-            //  N + SE -> NE
-            while self.n > 0 && self.se > 0 {
-                self.n -= 1;
-                self.se -= 1;
-                self.ne += 1;
-                changed = true;
-            }
+    fn end(&self) -> Cube {
+        self.cells[self.cells.len() - 1]
+    }
 
-            //  NE + S -> SE
-            while self.ne > 0 && self.n < 0 {
-                self.ne -= 1;
-                self.n += 1;
-                self.se += 1;
-                changed = true;
+    /// The first cell at the greatest distance from the origin
+    fn furthest(&self) -> Cube {
+        self.cells.iter().copied().fold(Cube::ORIGIN, |best, cell| {
+            if cell.length() > best.length() {
+                cell
+            } else {
+                best
             }
+        })
+    }
 
-            //  SE + SW -> S
-            while self.se > 0 && self.ne < 0 {
-                self.se -= 1;
-                self.ne += 1;
-                self.n -= 1;
-                changed = true;
-            }
+    /// How many times each cell was stood in; the origin counts once for the start
+    fn visits(&self) -> HashMap<Cube, usize> {
+        let mut visits = HashMap::new();
+        for cell in self.cells.iter().copied() {
+            *visits.entry(cell).or_insert(0) += 1;
+        }
+        visits
+    }
+}
 
-            //  S + NW -> SW
-            while self.n < 0 && self.se < 0 {
-                self.n += 1;
-                self.se += 1;
-                self.ne -= 1;
-                changed = true;
-            }
+/// The canonical shortest route from `from` back to the origin: at every step, the first
+/// direction (clockwise from north) that gets closer. A shortest route only ever uses two
+/// neighboring directions, so this lists all of one and then all of the other.
+fn path_home(from: Cube) -> Vec<Dir> {
+    let mut pos = from;
+    let mut path = Vec::with_capacity(from.length() as usize);
 
-            //  SW + N -> NW
-            while self.ne < 0 && self.n > 0 {
-                self.ne += 1;
-                self.n -= 1;
-                self.se -= 1;
-                changed = true;
-            }
+    while pos != Cube::ORIGIN {
+        let dir = Dir::ALL
+            .iter()
+            .copied()
+            .find(|&dir| pos.neighbor(dir).length() < pos.length())
+            .unwrap();
+        pos = pos.neighbor(dir);
+        path.push(dir);
+    }
 
-            //  NW + NE -> N
-            while self.se < 0 && self.ne > 0 {
-                self.se += 1;
-                self.ne -= 1;
-                self.n += 1;
-                changed = true;
-            }
+    path
+}
 
-            if !changed {
-                break;
-            }
+mod render {
+    use super::Walk;
+    use crate::hex::{Axial, Cube};
+
+    /// Flat-topped hexes on a character grid: each column is one q, and within it cells
+    /// sit every other row so that neighboring columns interleave. The origin is 'O', the
+    /// end 'X', and everywhere else walked through shows its visit count ('+' for 10 or more).
+    pub(super) fn to_ascii(walk: &Walk) -> String {
+        // "doubled" coordinates: going north is two rows up, north-east one up and one over
+        let place = |cell: Cube| (cell.q, 2 * cell.r + cell.q);
+
+        let visits = walk.visits();
+        let min_x = visits.keys().map(|&c| place(c).0).min().unwrap();
+        let max_x = visits.keys().map(|&c| place(c).0).max().unwrap();
+        let min_y = visits.keys().map(|&c| place(c).1).min().unwrap();
+        let max_y = visits.keys().map(|&c| place(c).1).max().unwrap();
+
+        let width = (2 * (max_x - min_x) + 1) as usize;
+        let height = (max_y - min_y + 1) as usize;
+        let mut grid = vec![vec![' '; width]; height];
+
+        for (&cell, &count) in visits.iter() {
+            let (x, y) = place(cell);
+            grid[(y - min_y) as usize][2 * (x - min_x) as usize] = match count {
+                0..=9 => std::char::from_digit(count as u32, 10).unwrap(),
+                _ => '+',
+            };
         }
 
-        self.n.abs() + self.ne.abs() + self.se.abs()
+        for (cell, mark) in [(walk.end(), 'X'), (Cube::ORIGIN, 'O')].iter().copied() {
+            let (x, y) = place(cell);
+            grid[(y - min_y) as usize][2 * (x - min_x) as usize] = mark;
+        }
+
+        grid.into_iter()
+            .map(|row| row.into_iter().collect::<String>().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
     }
-}
 
-fn parse_11a(input: &str) -> impl Iterator<Item = Dir> + '_ {
-    input.split(',').map(|token| match token {
-        "s" => Dir::S,
-        "se" => Dir::SE,
-        "ne" => Dir::NE,
-        "sw" => Dir::SW,
-        "n" => Dir::N,
-        "nw" => Dir::NW,
-        other => panic!("Unrecognized token {}", other),
-    })
-}
+    /// A heatmap of flat-topped hexagons, darker the more often a cell was visited, with
+    /// the route drawn over it from the green origin to the red end
+    pub(super) fn to_svg(walk: &Walk) -> String {
+        const SIZE: f64 = 10.0;
+        let sqrt3 = 3f64.sqrt();
+        let center = |cell: Cube| {
+            (
+                SIZE * 1.5 * cell.q as f64,
+                SIZE * sqrt3 * (cell.r as f64 + cell.q as f64 / 2.0),
+            )
+        };
+
+        let visits = walk.visits();
+        let most = visits.values().copied().max().unwrap() as f64;
 
-fn run_11a_with_input(input: &str) -> i32 {
-    let mut totals = Totals::default();
+        let centers: Vec<(f64, f64)> = visits.keys().map(|&cell| center(cell)).collect();
+        let min_x = centers.iter().map(|c| c.0).fold(f64::INFINITY, f64::min) - 2.0 * SIZE;
+        let max_x = centers
+            .iter()
+            .map(|c| c.0)
+            .fold(f64::NEG_INFINITY, f64::max)
+            + 2.0 * SIZE;
+        let min_y = centers.iter().map(|c| c.1).fold(f64::INFINITY, f64::min) - 2.0 * SIZE;
+        let max_y = centers
+            .iter()
+            .map(|c| c.1)
+            .fold(f64::NEG_INFINITY, f64::max)
+            + 2.0 * SIZE;
 
-    for dir in parse_11a(input) {
-        totals.and_dir(dir);
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{:.1} {:.1} {:.1} {:.1}\">\n",
+            min_x,
+            min_y,
+            max_x - min_x,
+            max_y - min_y
+        );
+
+        // sorted so the output doesn't depend on hash order
+        let mut cells: Vec<(Cube, usize)> = visits.into_iter().collect();
+        cells.sort_by_key(|&(cell, _)| (cell.q, cell.r));
+
+        for (cell, count) in cells {
+            let (x, y) = center(cell);
+            let corners: Vec<String> = (0..6)
+                .map(|i| {
+                    let angle = std::f64::consts::PI / 3.0 * i as f64;
+                    format!(
+                        "{:.1},{:.1}",
+                        x + SIZE * angle.cos(),
+                        y + SIZE * angle.sin()
+                    )
+                })
+                .collect();
+            out.push_str(&format!(
+                "  <polygon points=\"{}\" fill=\"#1f5fa8\" fill-opacity=\"{:.2}\"><title>{} visited {}</title></polygon>\n",
+                corners.join(" "),
+                0.15 + 0.85 * count as f64 / most,
+                Axial::from(cell),
+                count
+            ));
+        }
+
+        let points: Vec<String> = walk
+            .cells
+            .iter()
+            .map(|&cell| {
+                let (x, y) = center(cell);
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect();
+        out.push_str(&format!(
+            "  <polyline points=\"{}\" fill=\"none\" stroke=\"#d0342c\" stroke-width=\"1\"/>\n",
+            points.join(" ")
+        ));
+
+        for (cell, color) in [(Cube::ORIGIN, "#2e9e44"), (walk.end(), "#d0342c")].iter() {
+            let (x, y) = center(*cell);
+            out.push_str(&format!(
+                "  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\"/>\n",
+                x,
+                y,
+                SIZE / 2.0,
+                color
+            ));
+        }
+
+        out.push_str("</svg>");
+        out
     }
+}
+
+/// Where the child process ended up and how to get back, or its route drawn as "ascii" or "svg"
+pub fn path_11(input: &str, format: &str) -> String {
+    let walk = Walk::new(parse_11a(input));
 
-    totals.distance_from_origin()
+    match format {
+        "summary" => {
+            let end = walk.end();
+            let furthest = walk.furthest();
+            let visits = walk.visits();
+            let (busiest, most) = visits
+                .iter()
+                .max_by_key(|&(cell, count)| (*count, -cell.q, -cell.r))
+                .unwrap();
+            let home: Vec<&str> = path_home(end).into_iter().map(Dir::name).collect();
+            let count =
+                |n: usize, noun: &str| format!("{} {}{}", n, noun, if n == 1 { "" } else { "s" });
+
+            format!(
+                "Ended at {} ({} out)\nFurthest was {} ({} out)\n\
+                 Visited {} cells; {} the most, {}\nWay home: {}",
+                Axial::from(end),
+                count(end.length() as usize, "step"),
+                Axial::from(furthest),
+                count(furthest.length() as usize, "step"),
+                visits.len(),
+                Axial::from(*busiest),
+                count(*most, "time"),
+                home.join(",")
+            )
+        }
+        "ascii" => render::to_ascii(&walk),
+        "svg" => render::to_svg(&walk),
+        other => format!("Unrecognized path format {}", other),
+    }
 }
 
-pub fn run_11a() -> i32 {
-    run_11a_with_input(INPUT)
+fn format_cells<I: IntoIterator<Item = Cube>>(cells: I) -> String {
+    cells
+        .into_iter()
+        .map(|cell| Axial::from(cell).to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
-fn run_11b_with_input(input: &str) -> i32 {
-    let mut totals = Totals::default();
-    let mut max = 0;
+/// Hex grid calculator for checking routes by hand; cells are given in axial form as "q,r"
+pub fn hex_11(op: &str, args: &[String]) -> String {
+    fn raw(args: &[String], i: usize) -> Result<&str, String> {
+        args.get(i)
+            .map(String::as_str)
+            .ok_or_else(|| format!("Missing argument {}", i + 1))
+    }
 
-    for dir in parse_11a(input) {
-        totals.and_dir(dir);
-        max = max.max(totals.distance_from_origin());
+    fn cell(args: &[String], i: usize) -> Result<Cube, String> {
+        raw(args, i)?.parse::<Axial>().map(Cube::from)
     }
 
-    max
-}
+    fn number(args: &[String], i: usize) -> Result<i64, String> {
+        let arg = raw(args, i)?;
+        arg.parse()
+            .map_err(|_| format!("Expected a number, not {}", arg))
+    }
 
-pub fn run_11b() -> i32 {
-    run_11b_with_input(INPUT)
+    let result = match op {
+        "distance" => cell(args, 0)
+            .and_then(|a| Ok(a.distance(cell(args, 1)?)))
+            .map(|d| d.to_string()),
+        "neighbors" => cell(args, 0).map(|a| format_cells(a.neighbors().iter().copied())),
+        "ring" => cell(args, 0).and_then(|a| match number(args, 1)? {
+            radius if radius < 0 => Err(format!("Radius must not be negative, not {}", radius)),
+            radius => Ok(format_cells(a.ring(radius))),
+        }),
+        "line" => cell(args, 0).and_then(|a| Ok(format_cells(a.line(cell(args, 1)?)))),
+        "rotate" => cell(args, 0).and_then(|a| {
+            let steps = number(args, 1)?;
+            let center = if args.len() > 2 {
+                cell(args, 2)?
+            } else {
+                Cube::ORIGIN
+            };
+            Ok(format_cells(Some(a.rotate_about(center, steps))))
+        }),
+        "reflect" => cell(args, 0).and_then(|a| {
+            Ok(format_cells(Some(
+                a.reflect(raw(args, 1)?.parse::<Axis>()?),
+            )))
+        }),
+        other => Err(format!("Unrecognized operation {}", other)),
+    };
+
+    result.unwrap_or_else(|e| e)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// The original signed-counter approach, kept to check the cube distance against: opposite
+    /// moves cancel in the counts, then inefficient pairs are rewritten until none are left
+    #[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+    struct Totals {
+        ne: i32,
+        n: i32,
+        se: i32,
+    }
+
+    impl Totals {
+        fn and_dir(&mut self, dir: Dir) {
+            match dir {
+                Dir::NE => self.ne += 1,
+                Dir::N => self.n += 1,
+                Dir::SE => self.se += 1,
+
+                Dir::SW => self.ne -= 1,
+                Dir::S => self.n -= 1,
+                Dir::NW => self.se -= 1,
+            }
+        }
+
+        fn distance_from_origin(mut self) -> i32 {
+            loop {
+                let mut changed = false;
+
+                // Basically you can replace any inefficient path with a more efficient path
+                // Actual cancellations (NE+SW, SE+NW, E+W) have already been cancelled out and
+                // replaced by signed direction counts.
+                //      N
+                //  NW      NE
+                //  SW      SE
+                //      S
+                // Remaining inefficient paths (note SW is -NE and so on):
+
+                //  N + SE -> NE
+                while self.n > 0 && self.se > 0 {
+                    self.n -= 1;
+                    self.se -= 1;
+                    self.ne += 1;
+                    changed = true;
+                }
+
+                //  NE + S -> SE
+                while self.ne > 0 && self.n < 0 {
+                    self.ne -= 1;
+                    self.n += 1;
+                    self.se += 1;
+                    changed = true;
+                }
+
+                //  SE + SW -> S
+                while self.se > 0 && self.ne < 0 {
+                    self.se -= 1;
+                    self.ne += 1;
+                    self.n -= 1;
+                    changed = true;
+                }
+
+                //  S + NW -> SW
+                while self.n < 0 && self.se < 0 {
+                    self.n += 1;
+                    self.se += 1;
+                    self.ne -= 1;
+                    changed = true;
+                }
+
+                //  SW + N -> NW
+                while self.ne < 0 && self.n > 0 {
+                    self.ne += 1;
+                    self.n -= 1;
+                    self.se -= 1;
+                    changed = true;
+                }
+
+                //  NW + NE -> N
+                while self.se < 0 && self.ne > 0 {
+                    self.se += 1;
+                    self.ne -= 1;
+                    self.n += 1;
+                    changed = true;
+                }
+
+                if !changed {
+                    break;
+                }
+            }
+
+            self.n.abs() + self.ne.abs() + self.se.abs()
+        }
+    }
+
     #[test]
     fn sample_11a() {
         assert_eq!(run_11a_with_input("ne,ne,ne"), 3);
@@ -154,4 +422,123 @@ mod tests {
         assert_eq!(run_11a_with_input("se,n"), 1);
         assert_eq!(run_11a_with_input("se,sw,se,sw,sw"), 3);
     }
+
+    #[test]
+    fn paths_home() {
+        for input in [
+            "ne,ne,ne",
+            "ne,ne,s,s",
+            "se,sw,se,sw,sw",
+            "n,n,nw,sw,sw,s,se",
+        ]
+        .iter()
+        {
+            let end = Walk::new(parse_11a(input)).end();
+            let home = path_home(end);
+
+            assert_eq!(home.len() as i64, end.length());
+            let back = home.iter().fold(end, |pos, &dir| pos.neighbor(dir));
+            assert_eq!(back, Cube::ORIGIN);
+        }
+
+        assert!(path_home(Cube::ORIGIN).is_empty());
+        // grouped, in clockwise order from north
+        let from = Dir::S.offset() * 2 + Dir::SW.offset() * 3;
+        assert_eq!(
+            path_home(from),
+            vec![Dir::N, Dir::N, Dir::NE, Dir::NE, Dir::NE]
+        );
+    }
+
+    #[test]
+    fn path_summary_and_renders() {
+        let walk = Walk::new(parse_11a("n,n,s,se,s\n"));
+        let visits = walk.visits();
+
+        assert_eq!(walk.cells.len(), 6);
+        assert_eq!(visits[&Dir::N.offset()], 2);
+        assert_eq!(visits[&Cube::ORIGIN], 1);
+        assert_eq!(walk.furthest(), Dir::N.offset() * 2);
+
+        assert_eq!(
+            path_11("n,n,s,se,s", "summary"),
+            "Ended at 1,0 (1 step out)
+Furthest was 0,-2 (2 steps out)
+Visited 5 cells; 0,-1 the most, 2 times
+Way home: nw"
+        );
+        assert_eq!(
+            path_11("ne,sw", "summary"),
+            "Ended at 0,0 (0 steps out)
+Furthest was 1,-1 (1 step out)
+Visited 2 cells; 0,0 the most, 2 times
+Way home: "
+        );
+
+        // the trip north stacks up the left column; the step south-east sits half a row
+        // below it in the next column over, and the end half a row below the origin
+        assert_eq!(path_11("n,n,s,se,s", "ascii"), "1\n\n2\n  1\nO\n  X");
+
+        let svg = path_11("n,n,s,se,s", "svg");
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<polygon").count(), 5);
+        assert_eq!(svg.matches("<circle").count(), 2);
+        assert!(svg.contains("<title>0,-1 visited 2</title>"));
+
+        assert_eq!(path_11("n", "png"), "Unrecognized path format png");
+    }
+
+    #[test]
+    fn hex_calculator() {
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+
+        assert_eq!(hex_11("distance", &args("0,0 2,-3")), "3");
+        assert_eq!(hex_11("line", &args("0,0 0,-2")), "0,0 0,-1 0,-2");
+        assert_eq!(hex_11("ring", &args("1,1 0")), "1,1");
+        assert_eq!(hex_11("rotate", &args("1,0 3")), "-1,0");
+        assert_eq!(hex_11("rotate", &args("2,0 3 1,0")), "0,0");
+        assert_eq!(hex_11("reflect", &args("1,-1 q")), "1,0");
+        assert_eq!(hex_11("neighbors", &args("0,0")).split(' ').count(), 6);
+        assert_eq!(
+            hex_11("ring", &args("0,0 -1")),
+            "Radius must not be negative, not -1"
+        );
+        assert_eq!(hex_11("reflect", &args("1,-1 x")), "Unrecognized axis x");
+        assert_eq!(hex_11("line", &args("0,0")), "Missing argument 2");
+    }
+
+    #[test]
+    fn distance_matches_rewrites() {
+        // small xorshift so the random walks are the same every run
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..200 {
+            let len = next() % 300;
+            // bias each walk towards one direction so they don't all stay near the origin
+            let favourite = Dir::ALL[(next() % 6) as usize];
+
+            let mut totals = Totals::default();
+            let mut pos = Cube::ORIGIN;
+
+            for _ in 0..len {
+                let roll = next() % 9;
+                let dir = if roll < 6 {
+                    Dir::ALL[roll as usize]
+                } else {
+                    favourite
+                };
+
+                totals.and_dir(dir);
+                pos = pos.neighbor(dir);
+                assert_eq!(pos.length(), totals.distance_from_origin() as i64);
+            }
+        }
+    }
 }
@@ -116,6 +116,310 @@ pub fn run_12b() -> usize {
     run_12b_with_input(INPUT)
 }
 
+/// Undirected view of the pipe network; self-loops and repeated pipes are dropped
+/// so the graph algorithms below don't have to think about them
+struct Network {
+    adjacency: Vec<Vec<usize>>,
+}
+
+/// Weak spots of a network: pipes and programs whose removal splits a group
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct CutStructure {
+    bridges: Vec<(usize, usize)>,
+    articulation_points: Vec<usize>,
+}
+
+impl Network {
+    fn new(pipes: &[Pipe]) -> Network {
+        let size = pipes
+            .iter()
+            .flat_map(|pipe| std::iter::once(pipe.source).chain(pipe.connections.iter().copied()))
+            .max()
+            .map(|max| max + 1)
+            .unwrap_or(0);
+
+        let mut adjacency = vec![Vec::new(); size];
+
+        for pipe in pipes {
+            for connection in pipe.connections.iter().copied() {
+                if connection != pipe.source {
+                    adjacency[pipe.source].push(connection);
+                    adjacency[connection].push(pipe.source);
+                }
+            }
+        }
+
+        for neighbors in adjacency.iter_mut() {
+            neighbors.sort_unstable();
+            neighbors.dedup();
+        }
+
+        Network { adjacency }
+    }
+
+    fn len(&self) -> usize {
+        self.adjacency.len()
+    }
+
+    /// BFS distances from the start to everything in its group (None if unreachable)
+    fn distances_from(&self, start: usize) -> Vec<Option<usize>> {
+        let mut dist = vec![None; self.len()];
+        let mut to_process = VecDeque::new();
+
+        dist[start] = Some(0);
+        to_process.push_back(start);
+
+        while let Some(next) = to_process.pop_front() {
+            let next_dist = dist[next].unwrap() + 1;
+            for neighbor in self.adjacency[next].iter().copied() {
+                if dist[neighbor].is_none() {
+                    dist[neighbor] = Some(next_dist);
+                    to_process.push_back(neighbor);
+                }
+            }
+        }
+
+        dist
+    }
+
+    /// A shortest chain of programs from `from` to `to`, including both ends
+    fn shortest_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        if from >= self.len() || to >= self.len() {
+            return None;
+        }
+
+        let mut came_from: Vec<Option<usize>> = vec![None; self.len()];
+        let mut seen = vec![false; self.len()];
+        let mut to_process = VecDeque::new();
+
+        seen[from] = true;
+        to_process.push_back(from);
+
+        while let Some(next) = to_process.pop_front() {
+            if next == to {
+                let mut path = vec![to];
+                let mut curr = to;
+                while let Some(prev) = came_from[curr] {
+                    path.push(prev);
+                    curr = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for neighbor in self.adjacency[next].iter().copied() {
+                if !seen[neighbor] {
+                    seen[neighbor] = true;
+                    came_from[neighbor] = Some(next);
+                    to_process.push_back(neighbor);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Every group of programs, each sorted, ordered by lowest member
+    fn components(&self) -> Vec<Vec<usize>> {
+        let mut seen = vec![false; self.len()];
+        let mut components = Vec::new();
+
+        for start in 0..self.len() {
+            if seen[start] {
+                continue;
+            }
+
+            let mut component: Vec<usize> = self
+                .distances_from(start)
+                .into_iter()
+                .enumerate()
+                .filter(|(_, dist)| dist.is_some())
+                .map(|(program, _)| program)
+                .collect();
+
+            for program in component.iter().copied() {
+                seen[program] = true;
+            }
+
+            component.sort_unstable();
+            components.push(component);
+        }
+
+        components
+    }
+
+    /// Longest shortest path within a group; O(V*E) since it's a BFS from every member
+    fn diameter(&self, component: &[usize]) -> usize {
+        component
+            .iter()
+            .copied()
+            .map(|start| {
+                self.distances_from(start)
+                    .into_iter()
+                    .flatten()
+                    .max()
+                    .unwrap_or(0)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Tarjan's bridge / articulation point search. Iterative rather than recursive,
+    /// so a long chain of pipes can't blow the stack.
+    fn cut_structure(&self) -> CutStructure {
+        let n = self.len();
+
+        let mut discovered: Vec<Option<usize>> = vec![None; n];
+        let mut low = vec![0; n];
+        let mut is_articulation = vec![false; n];
+        let mut bridges = Vec::new();
+        let mut time = 0;
+
+        for root in 0..n {
+            if discovered[root].is_some() {
+                continue;
+            }
+
+            discovered[root] = Some(time);
+            low[root] = time;
+            time += 1;
+
+            let mut root_children = 0;
+
+            // (program, parent, index of the next neighbor to look at)
+            let mut stack: Vec<(usize, Option<usize>, usize)> = vec![(root, None, 0)];
+
+            while let Some(frame) = stack.last_mut() {
+                let (node, parent) = (frame.0, frame.1);
+
+                if let Some(child) = self.adjacency[node].get(frame.2).copied() {
+                    frame.2 += 1;
+
+                    if Some(child) == parent {
+                        continue;
+                    }
+
+                    match discovered[child] {
+                        Some(child_time) => {
+                            low[node] = low[node].min(child_time);
+                        }
+                        None => {
+                            discovered[child] = Some(time);
+                            low[child] = time;
+                            time += 1;
+
+                            if node == root {
+                                root_children += 1;
+                            }
+
+                            stack.push((child, Some(node), 0));
+                        }
+                    }
+                } else {
+                    stack.pop();
+
+                    if let Some(parent) = parent {
+                        low[parent] = low[parent].min(low[node]);
+
+                        let parent_time = discovered[parent].unwrap();
+                        if low[node] > parent_time {
+                            bridges.push((parent.min(node), parent.max(node)));
+                        }
+                        if parent != root && low[node] >= parent_time {
+                            is_articulation[parent] = true;
+                        }
+                    }
+                }
+            }
+
+            if root_children > 1 {
+                is_articulation[root] = true;
+            }
+        }
+
+        bridges.sort_unstable();
+
+        let articulation_points = is_articulation
+            .into_iter()
+            .enumerate()
+            .filter(|(_, is_cut)| *is_cut)
+            .map(|(program, _)| program)
+            .collect();
+
+        CutStructure {
+            bridges,
+            articulation_points,
+        }
+    }
+}
+
+fn join_programs(programs: &[usize], sep: &str) -> String {
+    programs
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<String>>()
+        .join(sep)
+}
+
+/// Human-readable robustness report for a pipe network; if a pair of programs is
+/// supplied, also includes a shortest path between them
+pub fn analyze_12(input: &str, path_query: Option<(usize, usize)>) -> String {
+    let pipes = parse::parse(input);
+    let network = Network::new(&pipes);
+
+    let mut out = String::new();
+
+    let components = network.components();
+    out.push_str(&format!(
+        "{} programs in {} groups\n",
+        network.len(),
+        components.len()
+    ));
+
+    for component in components.iter() {
+        out.push_str(&format!(
+            "  group of {} containing {}: diameter {}\n",
+            component.len(),
+            component[0],
+            network.diameter(component)
+        ));
+    }
+
+    let cuts = network.cut_structure();
+
+    let bridges: Vec<String> = cuts
+        .bridges
+        .iter()
+        .map(|(a, b)| format!("{} <-> {}", a, b))
+        .collect();
+    out.push_str(&format!(
+        "{} bridges: {}\n",
+        bridges.len(),
+        bridges.join(", ")
+    ));
+
+    out.push_str(&format!(
+        "{} articulation points: {}\n",
+        cuts.articulation_points.len(),
+        join_programs(&cuts.articulation_points, ", ")
+    ));
+
+    if let Some((from, to)) = path_query {
+        match network.shortest_path(from, to) {
+            Some(path) => out.push_str(&format!(
+                "Shortest path from {} to {} ({} pipes): {}\n",
+                from,
+                to,
+                path.len() - 1,
+                join_programs(&path, " -> ")
+            )),
+            None => out.push_str(&format!("No path from {} to {}\n", from, to)),
+        }
+    }
+
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,4 +449,47 @@ mod tests {
 
         assert_eq!(run_12b_with_input(input), 2);
     }
+
+    #[test]
+    fn sample_12_paths() {
+        let input = "0 <-> 2
+1 <-> 1
+2 <-> 0, 3, 4
+3 <-> 2, 4
+4 <-> 2, 3, 6
+5 <-> 6
+6 <-> 4, 5";
+
+        let network = Network::new(&parse::parse(input));
+
+        assert_eq!(network.shortest_path(0, 5), Some(vec![0, 2, 4, 6, 5]));
+        assert_eq!(network.shortest_path(3, 3), Some(vec![3]));
+        assert_eq!(network.shortest_path(0, 1), None);
+
+        let components = network.components();
+        assert_eq!(components, vec![vec![0, 2, 3, 4, 5, 6], vec![1]]);
+        assert_eq!(network.diameter(&components[0]), 4);
+        assert_eq!(network.diameter(&components[1]), 0);
+    }
+
+    #[test]
+    fn sample_12_cuts() {
+        let input = "0 <-> 2
+1 <-> 1
+2 <-> 0, 3, 4
+3 <-> 2, 4
+4 <-> 2, 3, 6
+5 <-> 6
+6 <-> 4, 5";
+
+        let network = Network::new(&parse::parse(input));
+
+        assert_eq!(
+            network.cut_structure(),
+            CutStructure {
+                bridges: vec![(0, 2), (4, 6), (5, 6)],
+                articulation_points: vec![2, 4, 6],
+            }
+        );
+    }
 }
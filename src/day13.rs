@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 const INPUT: &str = include_str!("input/13.txt");
 
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
@@ -52,6 +54,11 @@ impl ScannersState {
 
     fn move_scanners(&mut self) {
         for (scanner_ind, range) in self.scanners.iter().map(|s| s.range).enumerate() {
+            // nowhere to go; without this it would bounce between 0 and -1
+            if range <= 1 {
+                continue;
+            }
+
             let dir = self.scanner_dir[scanner_ind];
             let pos = self.scanner_pos[scanner_ind];
 
@@ -69,6 +76,34 @@ impl ScannersState {
         }
     }
 
+    /// Where the scanners are after `time` picoseconds, without stepping through them
+    fn at_time(scanners: Vec<Scanner>, time: u64) -> ScannersState {
+        let mut state = ScannersState::new(scanners);
+
+        if time == 0 {
+            return state;
+        }
+
+        for (scanner_ind, range) in state.scanners.iter().map(|s| s.range).enumerate() {
+            if range <= 1 {
+                continue;
+            }
+
+            let period = 2 * (range as u64 - 1);
+            let r = (time % period) as i32;
+
+            if r > 0 && r < range {
+                state.scanner_pos[scanner_ind] = r;
+                state.scanner_dir[scanner_ind] = 1;
+            } else {
+                state.scanner_pos[scanner_ind] = (period as i32 - r) % period as i32;
+                state.scanner_dir[scanner_ind] = -1;
+            }
+        }
+
+        state
+    }
+
     fn try_collision<F: FnMut(Scanner)>(&self, curr_depth: i32, mut on_collide: F) {
         for (scanner_ind, scanner) in self.scanners.iter().copied().enumerate() {
             if scanner.depth == curr_depth && self.scanner_pos[scanner_ind] == 0 {
@@ -103,9 +138,10 @@ pub fn run_13a() -> i32 {
     run_13a_with_input(INPUT)
 }
 
-// TODO perf: this takes 3.2s to run and the answer is like 3.8m
-// can probably improve it with M A T H but whatever
-fn run_13b_with_input(input: &str) -> i32 {
+/// The original step-by-step simulation for 13b; it takes a few seconds on the real input,
+/// so it's only kept around to check the analytical solver against
+#[cfg(test)]
+fn simulate_13b_with_input(input: &str) -> i32 {
     let scanners = parse::parse(input);
 
     let max_depth = scanners.iter().map(|s| s.depth).max().unwrap_or(0);
@@ -142,10 +178,356 @@ fn run_13b_with_input(input: &str) -> i32 {
     }
 }
 
-pub fn run_13b() -> i32 {
+/// Scanners only care about time modulo their period, so group them by period and
+/// record which residues of (delay mod period) would get the packet caught.
+fn forbidden_residues(scanners: &[Scanner]) -> Vec<(u64, Vec<bool>)> {
+    let mut by_period: HashMap<u64, Vec<bool>> = HashMap::new();
+
+    for scanner in scanners {
+        // a range-1 scanner never leaves the top, so every delay is caught
+        let period = if scanner.range <= 1 {
+            1
+        } else {
+            2 * (scanner.range as u64 - 1)
+        };
+
+        let depth = scanner.depth as u64 % period;
+        let residue = (period - depth) % period;
+
+        by_period
+            .entry(period)
+            .or_insert_with(|| vec![false; period as usize])[residue as usize] = true;
+    }
+
+    let mut out: Vec<(u64, Vec<bool>)> = by_period.into_iter().collect();
+    out.sort_by_key(|(period, _)| *period);
+    out
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Past this many residues, the wheel stops growing and the remaining periods get
+/// checked one candidate at a time instead
+const WHEEL_LIMIT: usize = 1 << 16;
+
+/// Ascending iterator over every delay that gets through the firewall uncaught.
+///
+/// The smallest periods are combined CRT-style into a "wheel": the list of residues
+/// modulo their LCM which dodge all of them. Candidates are then read off the wheel
+/// and sieved against whatever periods didn't fit.
+struct SafeDelays {
+    wheel: u64,
+    residues: Vec<u64>,
+    rest: Vec<(u64, Vec<bool>)>,
+    base: u64,
+    index: usize,
+    // LCM of every period, if it fits; if nothing is safe below it, nothing is safe at all
+    full_period: Option<u64>,
+    found_any: bool,
+}
+
+impl SafeDelays {
+    fn new(scanners: &[Scanner]) -> SafeDelays {
+        let mut wheel: u64 = 1;
+        let mut residues: Vec<u64> = vec![0];
+        let mut rest = Vec::new();
+        let mut full_period = Some(1_u64);
+
+        for (period, forbidden) in forbidden_residues(scanners) {
+            full_period =
+                full_period.and_then(|full| (full / gcd(full, period)).checked_mul(period));
+
+            let new_wheel = wheel / gcd(wheel, period) * period;
+            let copies = (new_wheel / wheel) as usize;
+
+            if residues.len().saturating_mul(copies) > WHEEL_LIMIT {
+                rest.push((period, forbidden));
+                continue;
+            }
+
+            let mut new_residues = Vec::with_capacity(residues.len() * copies);
+            for k in 0..copies as u64 {
+                for r in residues.iter().copied() {
+                    let delay = k * wheel + r;
+                    if !forbidden[(delay % period) as usize] {
+                        new_residues.push(delay);
+                    }
+                }
+            }
+
+            wheel = new_wheel;
+            residues = new_residues;
+        }
+
+        SafeDelays {
+            wheel,
+            residues,
+            rest,
+            base: 0,
+            index: 0,
+            full_period,
+            found_any: false,
+        }
+    }
+}
+
+impl Iterator for SafeDelays {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.residues.is_empty() {
+            return None;
+        }
+
+        loop {
+            if self.index == self.residues.len() {
+                self.index = 0;
+                self.base = self.base.checked_add(self.wheel)?;
+            }
+
+            let delay = self.base.checked_add(self.residues[self.index])?;
+            self.index += 1;
+
+            if !self.found_any && self.full_period.map(|full| delay >= full).unwrap_or(false) {
+                self.residues.clear();
+                return None;
+            }
+
+            if self
+                .rest
+                .iter()
+                .all(|(period, forbidden)| !forbidden[(delay % period) as usize])
+            {
+                self.found_any = true;
+                return Some(delay);
+            }
+        }
+    }
+}
+
+fn run_13b_with_input(input: &str) -> u64 {
+    let scanners = parse::parse(input);
+
+    SafeDelays::new(&scanners)
+        .next()
+        .expect("Every delay gets caught")
+}
+
+/// The first `count` delays that get through the firewall uncaught
+pub fn safe_delays_13(input: &str, count: usize) -> String {
+    let scanners = parse::parse(input);
+
+    let delays: Vec<String> = SafeDelays::new(&scanners)
+        .take(count)
+        .map(|delay| delay.to_string())
+        .collect();
+
+    if delays.is_empty() {
+        "No delay gets through uncaught".to_string()
+    } else {
+        delays.join("\n")
+    }
+}
+
+pub fn run_13b() -> u64 {
     run_13b_with_input(INPUT)
 }
 
+/// Draws the firewall the way the puzzle text does: one column per layer, scanners
+/// marked with S, and the packet's layer in parentheses instead of brackets.
+mod render {
+    use super::{Scanner, ScannersState};
+
+    pub(super) struct Frame {
+        pub(super) picosecond: u64,
+        pub(super) text: String,
+    }
+
+    fn render_frame(
+        state: &ScannersState,
+        layers: &[Option<usize>],
+        packet_depth: Option<i32>,
+    ) -> String {
+        let max_range = state.scanners.iter().map(|s| s.range).max().unwrap_or(1);
+
+        let mut header = String::new();
+        for depth in 0..layers.len() {
+            header.push_str(&format!(" {:<3}", depth));
+        }
+
+        let mut out = header.trim_end().to_string();
+        out.push('\n');
+
+        for row in 0..max_range.max(1) {
+            let mut line = String::new();
+
+            for (depth, layer) in layers.iter().enumerate() {
+                let has_packet = row == 0 && packet_depth == Some(depth as i32);
+                let (open, close) = if has_packet { ('(', ')') } else { ('[', ']') };
+
+                match layer {
+                    Some(scanner_ind) if row < state.scanners[*scanner_ind].range => {
+                        let occupied = state.scanner_pos[*scanner_ind] == row;
+                        line.push(open);
+                        line.push(if occupied { 'S' } else { ' ' });
+                        line.push(close);
+                    }
+                    None if row == 0 => {
+                        if has_packet {
+                            line.push_str("(.)");
+                        } else {
+                            line.push_str("...");
+                        }
+                    }
+                    _ => line.push_str("   "),
+                }
+
+                line.push(' ');
+            }
+
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// One frame per picosecond while the packet is inside the firewall, after it moves
+    /// and before the scanners do -- which is exactly when it can be caught. The scanners
+    /// are fast-forwarded through the delay, so large delays cost nothing.
+    pub(super) fn animate(scanners: Vec<Scanner>, delay: u64) -> Vec<Frame> {
+        let max_depth = scanners.iter().map(|s| s.depth).max().unwrap_or(0);
+
+        let mut layers: Vec<Option<usize>> = vec![None; max_depth as usize + 1];
+        for (scanner_ind, scanner) in scanners.iter().enumerate() {
+            layers[scanner.depth as usize] = Some(scanner_ind);
+        }
+
+        let mut state = ScannersState::at_time(scanners, delay);
+        let mut frames = Vec::new();
+        let mut severity = 0;
+        let mut caught = 0;
+
+        for depth in 0..=max_depth {
+            let mut text = render_frame(&state, &layers, Some(depth));
+
+            state.try_collision(depth, |scanner| {
+                severity += scanner.depth * scanner.range;
+                caught += 1;
+                text.push_str(&format!(
+                    "Caught by layer {} (severity {})\n",
+                    scanner.depth,
+                    scanner.depth * scanner.range
+                ));
+            });
+
+            frames.push(Frame {
+                picosecond: delay + depth as u64,
+                text,
+            });
+
+            state.move_scanners();
+        }
+
+        if let Some(last) = frames.last_mut() {
+            if caught == 0 {
+                last.text.push_str("Made it through uncaught\n");
+            } else {
+                last.text.push_str(&format!(
+                    "Caught {} times, total severity {}\n",
+                    caught, severity
+                ));
+            }
+        }
+
+        frames
+    }
+
+    pub(super) fn to_ascii(frames: &[Frame]) -> String {
+        let mut out = String::new();
+
+        for frame in frames {
+            out.push_str(&format!("Picosecond {}:\n", frame.picosecond));
+            out.push_str(&frame.text);
+            out.push('\n');
+        }
+
+        out
+    }
+
+    fn json_escape(text: &str) -> String {
+        let mut out = String::new();
+        for c in text.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\r\\n"),
+                c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
+            }
+        }
+        out
+    }
+
+    /// asciicast v2: a JSON header line, then one `[time, "o", data]` event per frame
+    pub(super) fn to_asciicast(frames: &[Frame], seconds_per_frame: f64) -> String {
+        let width = frames
+            .iter()
+            .flat_map(|frame| frame.text.lines())
+            .map(|line| line.len())
+            .max()
+            .unwrap_or(0)
+            .max(20);
+        let height = frames
+            .iter()
+            .map(|frame| frame.text.lines().count())
+            .max()
+            .unwrap_or(0)
+            + 2;
+
+        let mut out = format!(
+            "{{\"version\": 2, \"width\": {}, \"height\": {}}}\n",
+            width, height
+        );
+
+        for (i, frame) in frames.iter().enumerate() {
+            let screen = format!(
+                "\u{1b}[2J\u{1b}[HPicosecond {}:\n{}",
+                frame.picosecond, frame.text
+            );
+            out.push_str(&format!(
+                "[{:.3}, \"o\", \"{}\"]\n",
+                i as f64 * seconds_per_frame,
+                json_escape(&screen)
+            ));
+        }
+
+        out
+    }
+}
+
+/// Renders the packet's trip through the firewall after waiting `delay` picoseconds.
+/// With a cast path, writes an asciicast recording there instead of returning frames.
+pub fn animate_13(input: &str, delay: u64, cast_path: Option<&str>) -> String {
+    let scanners = parse::parse(input);
+    let frames = render::animate(scanners, delay);
+
+    match cast_path {
+        None => render::to_ascii(&frames),
+        Some(path) => {
+            std::fs::write(path, render::to_asciicast(&frames, 0.5))
+                .unwrap_or_else(|e| panic!("Could not write '{}': {}", path, e));
+            format!("Wrote {} frames to {}", frames.len(), path)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -169,4 +551,76 @@ mod tests {
 
         assert_eq!(run_13b_with_input(input), 10);
     }
+
+    #[test]
+    fn analytical_13b_matches_simulation() {
+        let inputs = [
+            "0: 3\n1: 2\n4: 4\n6: 4",
+            "0: 2\n1: 3\n2: 4",
+            "3: 2\n5: 5\n7: 3\n8: 6\n11: 4",
+            "0: 5\n2: 7\n4: 9\n5: 2\n9: 3",
+        ];
+
+        for input in inputs.iter() {
+            assert_eq!(
+                run_13b_with_input(input),
+                simulate_13b_with_input(input) as u64,
+                "Input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn sample_13_safe_delays() {
+        let scanners = parse::parse("0: 3\n1: 2\n4: 4\n6: 4");
+        let delays: Vec<u64> = SafeDelays::new(&scanners).take(3).collect();
+
+        // the pattern repeats every 12 picoseconds, and 10 is the only safe residue
+        assert_eq!(delays, vec![10, 22, 34]);
+
+        let scanners = parse::parse("0: 2\n1: 2");
+        assert_eq!(SafeDelays::new(&scanners).next(), None);
+
+        let scanners = parse::parse("3: 1");
+        assert_eq!(SafeDelays::new(&scanners).next(), None);
+    }
+
+    #[test]
+    fn scanners_at_time() {
+        let scanners = parse::parse("0: 3\n1: 2\n2: 1\n4: 4\n6: 4");
+        let mut state = ScannersState::new(scanners.clone());
+
+        for time in 0..30 {
+            let jumped = ScannersState::at_time(scanners.clone(), time);
+            assert_eq!(jumped.scanner_pos, state.scanner_pos, "Time {}", time);
+            assert_eq!(jumped.scanner_dir, state.scanner_dir, "Time {}", time);
+            state.move_scanners();
+        }
+    }
+
+    #[test]
+    fn sample_13_render() {
+        let frames = render::animate(parse::parse("0: 3\n1: 2\n4: 4\n6: 4"), 0);
+
+        assert_eq!(frames.len(), 7);
+        assert_eq!(
+            frames[0].text,
+            " 0   1   2   3   4   5   6
+(S) [S] ... ... [S] ... [S]
+[ ] [ ]         [ ]     [ ]
+[ ]             [ ]     [ ]
+                [ ]     [ ]
+Caught by layer 0 (severity 0)
+"
+        );
+        assert!(frames[6].text.contains("Caught by layer 6 (severity 24)"));
+        assert!(frames[6]
+            .text
+            .ends_with("Caught 2 times, total severity 24\n"));
+
+        let frames = render::animate(parse::parse("0: 3\n1: 2\n4: 4\n6: 4"), 10);
+        assert_eq!(frames[0].picosecond, 10);
+        assert!(frames[6].text.ends_with("Made it through uncaught\n"));
+    }
 }
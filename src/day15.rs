@@ -2,6 +2,46 @@ const INPUT: &str = include_str!("input/15.txt");
 
 const MODULUS: i64 = 2147483647;
 
+const JUDGE_MASK: i64 = (1 << 16) - 1;
+
+/// `base` to the power `exp`, mod MODULUS, by repeated squaring. Everything stays below
+/// MODULUS between steps so the products fit comfortably in an i64.
+fn pow_mod(base: i64, mut exp: u64) -> i64 {
+    let mut base = base % MODULUS;
+    let mut out = 1;
+
+    while exp > 0 {
+        if exp & 1 == 1 {
+            out = (out * base) % MODULUS;
+        }
+        base = (base * base) % MODULUS;
+        exp >>= 1;
+    }
+
+    out
+}
+
+/// How many values are generated at a time by the batched judge
+const BATCH: usize = 4096;
+
+/// How many independent chains of values `Generator::fill_lanes` works on at once
+const LANES: usize = 8;
+
+/// `x` mod MODULUS for any `x` below MODULUS^2, without dividing. MODULUS is the Mersenne
+/// prime 2^31 - 1, so 2^31 is 1 mod MODULUS, and the high bits can just be added onto the
+/// low 31; the sum is then under 2 * MODULUS, so one subtraction finishes it off.
+fn reduce(x: u64) -> u64 {
+    const M: u64 = MODULUS as u64;
+
+    let folded = (x & M) + (x >> 31);
+    if folded >= M {
+        folded - M
+    } else {
+        folded
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
 struct Generator {
     val: i64,
     factor: i64,
@@ -16,6 +56,15 @@ impl Generator {
         }
     }
 
+    /// The generator `n` steps on; the same as calling `next` n times, but O(log n), since
+    /// n steps just multiply the value by factor^n
+    fn skip(&self, n: u64) -> Generator {
+        Generator {
+            val: (self.val * pow_mod(self.factor, n)) % MODULUS,
+            factor: self.factor,
+        }
+    }
+
     fn next_checked(&self, mask: i64) -> Generator {
         let mut gen = self.next();
         while gen.val & mask != 0 {
@@ -23,6 +72,72 @@ impl Generator {
         }
         gen
     }
+
+    /// Writes the low 16 bits of the current value, then of each `next_checked(mask)` after
+    /// it, into `out`, and returns the generator just past the last one written. A mask of 0
+    /// lets every value through, the same as `next`.
+    fn fill(&self, mask: i64, out: &mut [u16]) -> Generator {
+        let factor = self.factor as u64;
+        let mask = mask as u64;
+        let mut val = self.val as u64;
+
+        for slot in out.iter_mut() {
+            *slot = val as u16;
+            val = reduce(val * factor);
+            while val & mask != 0 {
+                val = reduce(val * factor);
+            }
+        }
+
+        Generator {
+            val: val as i64,
+            factor: self.factor,
+        }
+    }
+
+    /// `fill` with nothing masked out. One value after another is a single chain of
+    /// dependent multiplications, so instead LANES chains run side by side, each starting a
+    /// step further on and jumping LANES steps at a time. Values and the jump all fit in 32
+    /// bits, and the reduction is folded twice rather than compared, so the lanes can be
+    /// worked on as one vector.
+    fn fill_lanes(&self, out: &mut [u16]) -> Generator {
+        const M: u64 = MODULUS as u64;
+
+        // the result is only ever MODULUS itself for a multiple of MODULUS, and the
+        // generators never hit 0 mod MODULUS, so this is exact here
+        let fold = |x: u64| {
+            let once = (x & M) + (x >> 31);
+            ((once & M) + (once >> 31)) as u32
+        };
+
+        let factor = self.factor as u64;
+        let jump = pow_mod(self.factor, LANES as u64) as u32;
+
+        let mut lanes = [self.val as u32; LANES];
+        for k in 1..LANES {
+            lanes[k] = fold(lanes[k - 1] as u64 * factor);
+        }
+
+        let mut chunks = out.chunks_exact_mut(LANES);
+        for chunk in &mut chunks {
+            for k in 0..LANES {
+                chunk[k] = lanes[k] as u16;
+                lanes[k] = fold(lanes[k] as u64 * jump as u64);
+            }
+        }
+
+        // the lanes now hold the next LANES values in order, so the leftovers take the first
+        // few and the one after those is where the generator carries on
+        let rest = chunks.into_remainder();
+        for (slot, lane) in rest.iter_mut().zip(lanes.iter()) {
+            *slot = *lane as u16;
+        }
+
+        Generator {
+            val: lanes[rest.len()] as i64,
+            factor: self.factor,
+        }
+    }
 }
 
 // lol so complicated to parse two lines
@@ -62,18 +177,12 @@ fn parse_input(input: &str) -> (Generator, Generator) {
     )
 }
 
-fn run_15a_with_input(input: &str) -> usize {
-    const MASK: i64 = (1 << 16) - 1;
-
-    let (mut a, mut b) = parse_input(input);
-
+/// How many of the first `pairs` pairs (counting the starting values) agree on their low 16 bits
+fn judge(mut a: Generator, mut b: Generator, pairs: u64) -> usize {
     let mut agreements = 0;
 
-    for _ in 0..40_000_000 {
-        let a_val = a.val & MASK;
-        let b_val = b.val & MASK;
-
-        if a_val == b_val {
+    for _ in 0..pairs {
+        if a.val & JUDGE_MASK == b.val & JUDGE_MASK {
             agreements += 1;
         }
 
@@ -84,39 +193,147 @@ fn run_15a_with_input(input: &str) -> usize {
     agreements
 }
 
-pub fn run_15a() -> usize {
-    run_15a_with_input(INPUT)
-}
-
-fn run_15b_with_input(input: &str) -> usize {
-    const MASK: i64 = (1 << 16) - 1;
+/// `judge` for part B, where each generator only hands over values passing its mask
+fn judge_checked(
+    mut a: Generator,
+    mut b: Generator,
+    pairs: u64,
+    (mask_a, mask_b): (i64, i64),
+) -> usize {
+    let mut agreements = 0;
 
-    const A_MASK: i64 = (1 << 2) - 1;
-    const B_MASK: i64 = (1 << 3) - 1;
+    for _ in 0..pairs {
+        if a.val & JUDGE_MASK == b.val & JUDGE_MASK {
+            agreements += 1;
+        }
 
-    let (mut a, mut b) = parse_input(input);
+        a = a.next_checked(mask_a);
+        b = b.next_checked(mask_b);
+    }
 
-    let mut agreements = 0;
+    agreements
+}
 
-    for _ in 0..5_000_000 {
-        let a_val = a.val & MASK;
-        let b_val = b.val & MASK;
+/// Same as `judge_checked` (or `judge`, with both masks 0, when `fill_lanes` can do the
+/// generating), but generating a batch of low halves at a time so the comparison is a tight
+/// loop over two arrays
+fn judge_batched(mut a: Generator, mut b: Generator, pairs: u64, masks: (i64, i64)) -> usize {
+    let mut buf_a = [0_u16; BATCH];
+    let mut buf_b = [0_u16; BATCH];
 
-        if a_val == b_val {
-            agreements += 1;
+    let mut agreements = 0;
+    let mut left = pairs;
+
+    while left > 0 {
+        let len = (left as usize).min(BATCH);
+        if masks == (0, 0) {
+            a = a.fill_lanes(&mut buf_a[..len]);
+            b = b.fill_lanes(&mut buf_b[..len]);
+        } else {
+            a = a.fill(masks.0, &mut buf_a[..len]);
+            b = b.fill(masks.1, &mut buf_b[..len]);
         }
 
-        a = a.next_checked(A_MASK);
-        b = b.next_checked(B_MASK);
+        agreements += buf_a[..len]
+            .iter()
+            .zip(buf_b[..len].iter())
+            .filter(|(x, y)| x == y)
+            .count();
+        left -= len as u64;
     }
 
     agreements
 }
 
+/// Same as `judge`, but with the pairs split into one contiguous chunk per thread; each
+/// thread jumps straight to the start of its chunk with `skip`
+fn judge_parallel(a: Generator, b: Generator, pairs: u64, threads: usize) -> usize {
+    let threads = (threads.max(1) as u64).min(pairs.max(1));
+    let chunk = pairs.div_ceil(threads);
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|i| {
+                let start = (i * chunk).min(pairs);
+                let len = chunk.min(pairs - start);
+                scope.spawn(move || judge_batched(a.skip(start), b.skip(start), len, (0, 0)))
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum()
+    })
+}
+
+fn run_15a_with_input(input: &str) -> usize {
+    let (a, b) = parse_input(input);
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    judge_parallel(a, b, 40_000_000, threads)
+}
+
+pub fn run_15a() -> usize {
+    run_15a_with_input(INPUT)
+}
+
+const PART_B_MASKS: (i64, i64) = ((1 << 2) - 1, (1 << 3) - 1);
+
+fn run_15b_with_input(input: &str) -> usize {
+    let (a, b) = parse_input(input);
+
+    judge_batched(a, b, 5_000_000, PART_B_MASKS)
+}
+
 pub fn run_15b() -> usize {
     run_15b_with_input(INPUT)
 }
 
+/// Times the one-step-at-a-time judges against the batched ones, single threaded, for both parts
+pub fn bench_15(input: &str, runs: usize) -> String {
+    let (a, b) = parse_input(input);
+    let runs = runs.max(1);
+
+    // the generators go through black_box so a judge that got inlined can't be worked out
+    // once and hoisted out of the loop
+    let time = |judge: &dyn Fn(Generator, Generator) -> usize| {
+        let start = std::time::Instant::now();
+        let mut agreements = 0;
+        for _ in 0..runs {
+            agreements = judge(std::hint::black_box(a), std::hint::black_box(b));
+        }
+        (agreements, start.elapsed().as_secs_f64() / runs as f64)
+    };
+
+    let parts: [(&str, u64, (i64, i64)); 2] = [
+        ("Part A", 40_000_000, (0, 0)),
+        ("Part B", 5_000_000, PART_B_MASKS),
+    ];
+
+    let mut out = Vec::new();
+    for (name, pairs, masks) in parts.iter().copied() {
+        let (stepped, stepped_time) = if masks == (0, 0) {
+            time(&|a, b| judge(a, b, pairs))
+        } else {
+            time(&|a, b| judge_checked(a, b, pairs, masks))
+        };
+        let (batched, batched_time) = time(&|a, b| judge_batched(a, b, pairs, masks));
+
+        assert_eq!(stepped, batched, "Judges disagree");
+        out.push(format!(
+            "{}: {} agreements\n  Stepped: {:.4}s per run\n  Batched: {:.4}s per run\n  Speedup: {:.1}x",
+            name,
+            batched,
+            stepped_time,
+            batched_time,
+            stepped_time / batched_time
+        ));
+    }
+
+    out.join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -136,4 +353,96 @@ Generator B starts with 8921";
 
         assert_eq!(run_15b_with_input(input), 309);
     }
+
+    #[test]
+    fn skip_matches_stepping() {
+        let start = Generator {
+            val: 65,
+            factor: 16807,
+        };
+
+        let mut stepped = start;
+        for n in 0..1000 {
+            assert_eq!(start.skip(n).val, stepped.val, "Skipping {}", n);
+            stepped = stepped.next();
+        }
+
+        // the multiplicative group mod a prime has order MODULUS - 1
+        assert_eq!(start.skip(MODULUS as u64 - 1).val, 65);
+        assert_eq!(start.skip(5).skip(7).val, start.skip(12).val);
+    }
+
+    #[test]
+    fn parallel_judge_matches_serial() {
+        let (a, b) = parse_input(
+            "Generator A starts with 65
+Generator B starts with 8921",
+        );
+
+        assert_eq!(judge(a, b, 5), 1);
+
+        let serial = judge(a, b, 100_003);
+        for threads in [1, 2, 3, 7, 16].iter().copied() {
+            assert_eq!(judge_parallel(a, b, 100_003, threads), serial);
+        }
+        assert_eq!(judge_parallel(a, b, 0, 4), 0);
+        assert_eq!(judge_parallel(a, b, 3, 8), judge(a, b, 3));
+    }
+
+    #[test]
+    fn reduction_and_batches() {
+        for x in [0, 1, 2147483646, 2147483647, 2147483648, 1 << 40, 1 << 61].iter() {
+            assert_eq!(reduce(*x), x % MODULUS as u64, "Reducing {}", x);
+        }
+        let largest = (MODULUS as u64 - 1) * (MODULUS as u64 - 1);
+        assert_eq!(reduce(largest), largest % MODULUS as u64);
+
+        let start = Generator {
+            val: 8921,
+            factor: 48271,
+        };
+
+        let mut buf = [0_u16; 10];
+        let after = start.fill(0, &mut buf);
+        let mut gen = start;
+        for &low in buf.iter() {
+            assert_eq!(low as i64, gen.val & JUDGE_MASK);
+            gen = gen.next();
+        }
+        assert_eq!(after.val, gen.val);
+
+        // the lanes agree with one value at a time, however the length splits between them
+        for len in [0, 1, LANES - 1, LANES, 3 * LANES + 5].iter().copied() {
+            let mut lanes = vec![0_u16; len];
+            let mut serial = vec![0_u16; len];
+            let after = start.fill_lanes(&mut lanes);
+            assert_eq!(after.val, start.fill(0, &mut serial).val, "Length {}", len);
+            assert_eq!(lanes, serial, "Length {}", len);
+        }
+
+        let after = start.fill(7, &mut buf);
+        let mut gen = start;
+        for &low in buf.iter() {
+            assert_eq!(low as i64, gen.val & JUDGE_MASK);
+            gen = gen.next_checked(7);
+        }
+        assert_eq!(after.val, gen.val);
+    }
+
+    #[test]
+    fn batched_judges_match_stepped() {
+        let (a, b) = parse_input(
+            "Generator A starts with 65
+Generator B starts with 8921",
+        );
+
+        // across a few batch boundaries
+        let pairs = 3 * BATCH as u64 + 17;
+        assert_eq!(judge_batched(a, b, pairs, (0, 0)), judge(a, b, pairs));
+        assert_eq!(
+            judge_batched(a, b, pairs, PART_B_MASKS),
+            judge_checked(a, b, pairs, PART_B_MASKS)
+        );
+        assert_eq!(judge_batched(a, b, 0, (0, 0)), 0);
+    }
 }
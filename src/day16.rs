@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use crate::cycles::{nth_state, Method};
 
 const INPUT: &str = include_str!("input/16.txt");
 
@@ -9,6 +9,25 @@ enum DanceMove {
     SwapName(char, char),
 }
 
+impl std::fmt::Display for DanceMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DanceMove::Spin(amt) => write!(f, "s{}", amt),
+            DanceMove::SwapPos(a, b) => write!(f, "x{}/{}", a, b),
+            DanceMove::SwapName(a, b) => write!(f, "p{}/{}", a, b),
+        }
+    }
+}
+
+/// The moves in the puzzle's own comma-separated format, so `parse::parse` reads them back
+fn format_moves(moves: &[DanceMove]) -> String {
+    moves
+        .iter()
+        .map(|dm| dm.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 mod parse {
     use super::DanceMove;
 
@@ -41,7 +60,12 @@ mod parse {
         full(input)
     }
 
+    /// An empty dance is no moves at all, which is what `format_moves` writes for one
     pub(super) fn parse(input: &str) -> Vec<DanceMove> {
+        if input.trim().is_empty() {
+            return Vec::new();
+        }
+
         input
             .split(',')
             .map(|token| parse_dance_move(token).unwrap().1)
@@ -95,6 +119,213 @@ fn process_move(dance_move: DanceMove, cohort: &mut [char]) {
     }
 }
 
+/// A whole dance compiled down to two permutations, which between them say where every
+/// dancer ends up. Spins and position swaps only ever move whoever is in a spot, and
+/// partner swaps only ever trade names, so the two kinds of move can be collected
+/// separately no matter how they are interleaved.
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct Dance {
+    /// After the dance, spot i holds whoever started in spot `positions[i]`
+    positions: Vec<usize>,
+    /// The dancer who started out named 'a' + c finishes named 'a' + `names[c]`
+    names: Vec<usize>,
+}
+
+impl Dance {
+    fn identity(cohort_size: usize) -> Dance {
+        Dance {
+            positions: (0..cohort_size).collect(),
+            names: (0..cohort_size).collect(),
+        }
+    }
+
+    fn new(moves: &[DanceMove], cohort_size: usize) -> Result<Dance, String> {
+        if cohort_size == 0 || cohort_size > 26 {
+            return Err(format!(
+                "Cohorts need between 1 and 26 dancers, not {}",
+                cohort_size
+            ));
+        }
+
+        let mut dance = Dance::identity(cohort_size);
+        let name_index = |name: char| {
+            let index = (name as usize).wrapping_sub('a' as usize);
+            if index < cohort_size {
+                Ok(index)
+            } else {
+                Err(format!(
+                    "No dancer named {} in a cohort of {}",
+                    name, cohort_size
+                ))
+            }
+        };
+
+        // positional moves shuffle the starting spots exactly as they'd shuffle dancers
+        for dance_move in moves.iter().copied() {
+            match dance_move {
+                DanceMove::Spin(amt) => dance.positions.rotate_right(amt % cohort_size),
+                DanceMove::SwapPos(a, b) if a.max(b) < cohort_size => dance.positions.swap(a, b),
+                DanceMove::SwapPos(a, b) => {
+                    return Err(format!(
+                        "Can't swap positions {} and {} in a cohort of {}",
+                        a, b, cohort_size
+                    ))
+                }
+                DanceMove::SwapName(a, b) => {
+                    let (a, b) = (name_index(a)?, name_index(b)?);
+                    // whoever is currently called a is now called b, and vice versa
+                    for name in dance.names.iter_mut() {
+                        if *name == a {
+                            *name = b;
+                        } else if *name == b {
+                            *name = a;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(dance)
+    }
+
+    /// This dance followed by `other`
+    fn then(&self, other: &Dance) -> Dance {
+        Dance {
+            positions: other.positions.iter().map(|&i| self.positions[i]).collect(),
+            names: self.names.iter().map(|&c| other.names[c]).collect(),
+        }
+    }
+
+    /// The dance repeated n times, by repeated squaring
+    fn pow(&self, mut n: u64) -> Dance {
+        let mut out = Dance::identity(self.positions.len());
+        let mut square = self.clone();
+
+        while n > 0 {
+            if n & 1 == 1 {
+                out = out.then(&square);
+            }
+            square = square.then(&square);
+            n >>= 1;
+        }
+
+        out
+    }
+
+    /// The shortest list of moves with a single spin at most that does the same as this
+    /// dance: the spin and position swaps first, then the partner swaps
+    fn to_moves(&self) -> Vec<DanceMove> {
+        let cohort_size = self.positions.len();
+        let identity: Vec<usize> = (0..cohort_size).collect();
+        let mut moves = positional_moves(&identity, &self.positions);
+
+        // the same trick as for positions: fix up each dancer's name in turn, and since the
+        // swap only touches the two names involved, the ones already fixed stay fixed
+        let mut names = identity;
+        for c in 0..cohort_size {
+            let (have, want) = (names[c], self.names[c]);
+            if have != want {
+                for name in names.iter_mut() {
+                    if *name == have {
+                        *name = want;
+                    } else if *name == want {
+                        *name = have;
+                    }
+                }
+                moves.push(DanceMove::SwapName(
+                    (b'a' + have as u8) as char,
+                    (b'a' + want as u8) as char,
+                ));
+            }
+        }
+
+        moves
+    }
+
+    fn apply(&self, cohort: &[char]) -> Vec<char> {
+        self.positions
+            .iter()
+            .map(|&i| {
+                let name = cohort[i] as usize - 'a' as usize;
+                (b'a' + self.names[name] as u8) as char
+            })
+            .collect()
+    }
+}
+
+/// The fewest spins and position swaps which rearrange `from` into `to`, both holding the
+/// same distinct items: for each possible spin, swap every item into place in turn (which
+/// takes one swap fewer than the length of each loop of misplaced items) and keep the best
+fn positional_moves<T: Copy + Eq>(from: &[T], to: &[T]) -> Vec<DanceMove> {
+    let mut best: Option<Vec<DanceMove>> = None;
+
+    for amt in 0..from.len() {
+        let mut cohort = from.to_vec();
+        cohort.rotate_right(amt);
+
+        let mut moves = Vec::new();
+        if amt > 0 {
+            moves.push(DanceMove::Spin(amt));
+        }
+
+        for i in 0..cohort.len() {
+            if cohort[i] != to[i] {
+                let j = (i + 1..cohort.len()).find(|&j| cohort[j] == to[i]).unwrap();
+                cohort.swap(i, j);
+                moves.push(DanceMove::SwapPos(i, j));
+            }
+        }
+
+        if best.as_ref().is_none_or(|best| moves.len() < best.len()) {
+            best = Some(moves);
+        }
+    }
+
+    best.unwrap_or_default()
+}
+
+/// An equivalent dance which is as short as one spin, then position swaps, then partner
+/// swaps can be; consecutive spins merge and swaps that undo each other disappear
+fn simplify(moves: &[DanceMove], cohort_size: usize) -> Result<Vec<DanceMove>, String> {
+    Dance::new(moves, cohort_size).map(|dance| dance.to_moves())
+}
+
+/// The dance which puts everyone back: every move undone, last move first
+fn invert(moves: &[DanceMove], cohort_size: usize) -> Result<Vec<DanceMove>, String> {
+    // also checks every move fits the cohort
+    Dance::new(moves, cohort_size)?;
+
+    Ok(moves
+        .iter()
+        .rev()
+        .filter_map(|dm| match *dm {
+            DanceMove::Spin(amt) if amt % cohort_size == 0 => None,
+            DanceMove::Spin(amt) => Some(DanceMove::Spin(cohort_size - amt % cohort_size)),
+            swap => Some(swap),
+        })
+        .collect())
+}
+
+/// A short dance which turns the line-up `from` into `to`, using spins and position swaps
+fn diff(from: &str, to: &str) -> Result<Vec<DanceMove>, String> {
+    let from: Vec<char> = from.trim().chars().collect();
+    let to: Vec<char> = to.trim().chars().collect();
+
+    let mut sorted_from = from.clone();
+    sorted_from.sort_unstable();
+    let mut sorted_to = to.clone();
+    sorted_to.sort_unstable();
+
+    if sorted_from != sorted_to {
+        return Err("Both line-ups need the same dancers".to_string());
+    }
+    if sorted_from.windows(2).any(|pair| pair[0] == pair[1]) {
+        return Err("Every dancer needs a different name".to_string());
+    }
+
+    Ok(positional_moves(&from, &to))
+}
+
 fn make_cohort(cohort_size: usize) -> Vec<char> {
     let mut cohort = Vec::with_capacity(cohort_size);
     let mut next_char = 'a' as u8;
@@ -133,41 +364,69 @@ pub fn run_16a() -> String {
 
 fn run_16b_with_input(input: &str, cohort_size: usize) -> String {
     let moves = parse::parse(input);
+    let dance = Dance::new(&moves, cohort_size).unwrap();
 
-    let mut seen: HashMap<Vec<char>, usize> = HashMap::new();
-
-    let mut cohort = make_cohort(cohort_size);
-
-    for loop_counter in 0..1_000_000_000 {
-        if let Some(last_time) = seen.insert(cohort.clone(), loop_counter) {
-            println!(
-                "Saw a repeat; at ctr {} reach stage {}",
-                loop_counter, last_time
-            );
+    cohort_str(&dance.pow(1_000_000_000).apply(&make_cohort(cohort_size)))
+}
 
-            let loop_length = loop_counter - last_time;
-            let remaining = (1_000_000_000 - loop_counter) % loop_length;
+pub fn run_16b() -> String {
+    run_16b_with_input(INPUT, 16)
+}
 
-            for _ in 0..remaining {
-                for dm in &moves {
-                    process_move(*dm, &mut cohort);
-                }
-            }
+/// Dances `times` times by raising the compiled dance to that power, and for comparison
+/// by stepping the moves one dance at a time until the cohort repeats
+pub fn dance_16(input: &str, times: u64, cohort_size: usize) -> String {
+    let moves = parse::parse(input);
+    let start = make_cohort(cohort_size);
 
-            return cohort_str(&cohort);
-        }
+    let timer = std::time::Instant::now();
+    let dance = match Dance::new(&moves, cohort_size) {
+        Ok(dance) => dance,
+        Err(e) => return e,
+    };
+    let powered = cohort_str(&dance.pow(times).apply(&start));
+    let pow_time = timer.elapsed().as_secs_f64();
 
+    let timer = std::time::Instant::now();
+    let step = |cohort: &Vec<char>| {
+        let mut next = cohort.clone();
         for dm in &moves {
-            process_move(*dm, &mut cohort);
+            process_move(*dm, &mut next);
         }
-    }
+        next
+    };
+    let stepped = cohort_str(&nth_state(Method::Brent, &start, step, times));
+    let cycle_time = timer.elapsed().as_secs_f64();
+
+    assert_eq!(powered, stepped, "Dances disagree");
+    format!(
+        "{}\nPower:       {:.6}s\nCycle-find:  {:.6}s",
+        powered, pow_time, cycle_time
+    )
+}
 
-    // in practice this would never be reached, because you wouldn't let it run for a billion iterations
-    cohort_str(&cohort)
+/// Rewrites a dance as its "simplified" or "inverse" equivalent, in the same text format
+pub fn rewrite_16(input: &str, op: &str, cohort_size: usize) -> String {
+    let moves = parse::parse(input.trim());
+
+    let rewritten = match op {
+        "simplify" => simplify(&moves, cohort_size),
+        "inverse" => invert(&moves, cohort_size),
+        other => Err(format!("Unrecognized rewrite {}", other)),
+    };
+
+    match rewritten {
+        Ok(moves) => format_moves(&moves),
+        Err(e) => e,
+    }
 }
 
-pub fn run_16b() -> String {
-    run_16b_with_input(INPUT, 16)
+/// A short dance taking one line-up to another, in the puzzle's text format
+pub fn diff_16(from: &str, to: &str) -> String {
+    match diff(from, to) {
+        Ok(moves) => format_moves(&moves),
+        Err(e) => e,
+    }
 }
 
 #[cfg(test)]
@@ -182,4 +441,91 @@ mod test {
 
         assert_eq!(expected, &actual);
     }
+
+    #[test]
+    fn dance_algebra() {
+        let moves = parse::parse("s1,x3/4,pe/b");
+        let dance = Dance::new(&moves, 5).unwrap();
+        let start = make_cohort(5);
+
+        assert_eq!(cohort_str(&dance.apply(&start)), "baedc");
+        assert_eq!(cohort_str(&dance.pow(2).apply(&start)), "ceadb");
+        assert_eq!(dance.pow(0), Dance::identity(5));
+        assert_eq!(dance.then(&dance.pow(2)), dance.pow(3));
+
+        // stepping the moves and raising the compiled dance agree, for any number of rounds
+        let moves = parse::parse("x0/3,s3,pa/e,x2/4,pb/d,s1,x1/6,pg/a");
+        let dance = Dance::new(&moves, 7).unwrap();
+        let mut cohort = make_cohort(7);
+        for n in 0..50 {
+            assert_eq!(dance.pow(n).apply(&make_cohort(7)), cohort, "Round {}", n);
+            for dm in moves.iter() {
+                process_move(*dm, &mut cohort);
+            }
+        }
+
+        // fast even when there are far too many rounds to step through
+        let huge = dance.pow(1_000_000_000_000_000_000);
+        assert_eq!(huge.apply(&make_cohort(7)).len(), 7);
+
+        assert!(Dance::new(&parse::parse("x1/5"), 5).is_err());
+        assert!(Dance::new(&parse::parse("pa/f"), 5).is_err());
+        assert!(Dance::new(&[], 27).is_err());
+    }
+
+    fn run(moves: &[DanceMove], cohort: &str) -> String {
+        let mut cohort: Vec<char> = cohort.chars().collect();
+        for dm in moves.iter() {
+            process_move(*dm, &mut cohort);
+        }
+        cohort_str(&cohort)
+    }
+
+    #[test]
+    fn simplify_moves() {
+        let sample = parse::parse("s1,x3/4,pe/b");
+        assert_eq!(format_moves(&sample), "s1,x3/4,pe/b");
+
+        let simple = simplify(&sample, 5).unwrap();
+        assert_eq!(run(&simple, "abcde"), "baedc");
+        assert!(simple.len() <= sample.len());
+
+        assert_eq!(rewrite_16("s1,s2,s4", "simplify", 5), "s2");
+        assert_eq!(rewrite_16("x0/1,pa/c,x1/0,pc/a", "simplify", 5), "");
+        assert!(parse::parse(&rewrite_16("x0/1,pa/c,x1/0,pc/a", "simplify", 5)).is_empty());
+        assert_eq!(rewrite_16("", "inverse", 5), "");
+        assert_eq!(rewrite_16("x0/1,x1/2,x0/2", "simplify", 3), "x1/2");
+
+        let long = parse::parse("x0/3,s3,pa/e,x2/4,pb/d,s1,x1/6,pg/a,s5,x5/0,pf/c,s2");
+        let simple = parse::parse(&rewrite_16(
+            "x0/3,s3,pa/e,x2/4,pb/d,s1,x1/6,pg/a,s5,x5/0,pf/c,s2",
+            "simplify",
+            7,
+        ));
+        assert_eq!(run(&simple, "abcdefg"), run(&long, "abcdefg"));
+        assert!(simple.len() < long.len());
+        assert_eq!(simplify(&simple, 7).unwrap(), simple);
+    }
+
+    #[test]
+    fn inverse_and_diff() {
+        let text = "x0/3,s3,pa/e,x2/4,pb/d,s1,x1/6,pg/a,s7";
+        let moves = parse::parse(text);
+        let inverse = parse::parse(&rewrite_16(text, "inverse", 7));
+
+        let after = run(&moves, "abcdefg");
+        assert_eq!(run(&inverse, &after), "abcdefg");
+        assert_eq!(run(&moves, &run(&inverse, "gfedcba")), "gfedcba");
+
+        let moves = diff("abcde", "baedc").unwrap();
+        assert_eq!(run(&moves, "abcde"), "baedc");
+        assert_eq!(diff_16("abcde", "eabcd"), "s1");
+        assert_eq!(diff_16("abc", "abc"), "");
+        assert_eq!(run(&parse::parse(&diff_16("abc", "abc")), "abc"), "abc");
+        assert_eq!(diff_16("abc", "abd"), "Both line-ups need the same dancers");
+        assert_eq!(diff_16("aab", "aba"), "Every dancer needs a different name");
+
+        let moves = parse::parse(&diff_16("kbednhopmfcjilag", "fbmcgdnjakpioelh"));
+        assert_eq!(run(&moves, "kbednhopmfcjilag"), "fbmcgdnjakpioelh");
+    }
 }
@@ -1,50 +1,304 @@
 const INPUT: usize = 329;
 
-struct CircularBuffer {
-    // data is literally a map from idx -> succ(idx)
-    data: Vec<usize>,
-    pos: usize,
-}
+mod rope {
+    //! A sequence of distinct values supporting insertion anywhere, lookup by position and
+    //! finding where a value is, all in O(log n): a B-tree whose branches keep the number of
+    //! values under each child, with a map from each value back to the leaf holding it.
+
+    const LEAF_MAX: usize = 512;
+    const BRANCH_MAX: usize = 64;
+    const NONE: u32 = u32::MAX;
+
+    enum Node {
+        Leaf(Vec<u32>),
+        Branch {
+            children: Vec<u32>,
+            /// How many values are under each child
+            counts: Vec<usize>,
+        },
+    }
+
+    pub(super) struct Rope {
+        nodes: Vec<Node>,
+        parents: Vec<u32>,
+        root: u32,
+        /// The leaf each value is in, indexed by value
+        leaf_of: Vec<u32>,
+        len: usize,
+    }
+
+    impl Rope {
+        pub(super) fn new() -> Rope {
+            Rope {
+                nodes: vec![Node::Leaf(Vec::new())],
+                parents: vec![NONE],
+                root: 0,
+                leaf_of: Vec::new(),
+                len: 0,
+            }
+        }
+
+        pub(super) fn len(&self) -> usize {
+            self.len
+        }
+
+        fn count(&self, node: u32) -> usize {
+            match &self.nodes[node as usize] {
+                Node::Leaf(values) => values.len(),
+                Node::Branch { counts, .. } => counts.iter().sum(),
+            }
+        }
+
+        /// Which child of a branch holds `index`, and the index within that child. With
+        /// `inclusive`, an index just past the end of a child counts as in it, for appending.
+        fn find_child(counts: &[usize], mut index: usize, inclusive: bool) -> (usize, usize) {
+            let last = counts.len() - 1;
+            for (slot, &count) in counts.iter().enumerate() {
+                if index < count || (inclusive && index == count) || slot == last {
+                    return (slot, index);
+                }
+                index -= count;
+            }
+            unreachable!()
+        }
+
+        pub(super) fn get(&self, index: usize) -> Option<u32> {
+            if index >= self.len {
+                return None;
+            }
+
+            let (mut node, mut index) = (self.root, index);
+            loop {
+                match &self.nodes[node as usize] {
+                    Node::Leaf(values) => return Some(values[index]),
+                    Node::Branch { children, counts } => {
+                        let (slot, rest) = Rope::find_child(counts, index, false);
+                        node = children[slot];
+                        index = rest;
+                    }
+                }
+            }
+        }
+
+        /// Where `value` is in the sequence, if it's there at all
+        pub(super) fn position(&self, value: u32) -> Option<usize> {
+            let leaf = *self.leaf_of.get(value as usize)?;
+            if leaf == NONE {
+                return None;
+            }
+
+            let mut position = match &self.nodes[leaf as usize] {
+                Node::Leaf(values) => values.iter().position(|&v| v == value)?,
+                Node::Branch { .. } => unreachable!(),
+            };
+
+            // add on everything in earlier siblings, all the way up
+            let mut node = leaf;
+            while self.parents[node as usize] != NONE {
+                let parent = self.parents[node as usize];
+                if let Node::Branch { children, counts } = &self.nodes[parent as usize] {
+                    let slot = children.iter().position(|&c| c == node).unwrap();
+                    position += counts[..slot].iter().sum::<usize>();
+                }
+                node = parent;
+            }
+
+            Some(position)
+        }
+
+        /// Puts `value`, which mustn't be in the sequence already, at `index`
+        pub(super) fn insert(&mut self, index: usize, value: u32) {
+            assert!(index <= self.len, "Index {} out of bounds", index);
+
+            // walk down, counting the new value in on the way
+            let (mut node, mut index) = (self.root, index);
+            while let Node::Branch { children, counts } = &mut self.nodes[node as usize] {
+                let (slot, rest) = Rope::find_child(counts, index, true);
+                counts[slot] += 1;
+                node = children[slot];
+                index = rest;
+            }
+
+            let full = match &mut self.nodes[node as usize] {
+                Node::Leaf(values) => {
+                    values.insert(index, value);
+                    values.len() > LEAF_MAX
+                }
+                Node::Branch { .. } => unreachable!(),
+            };
+
+            if self.leaf_of.len() <= value as usize {
+                self.leaf_of.resize(value as usize + 1, NONE);
+            }
+            self.leaf_of[value as usize] = node;
+            self.len += 1;
+
+            if full {
+                self.split(node);
+            }
+        }
+
+        /// Moves the back half of an overfull node into a new sibling, splitting the parent
+        /// in turn if that overfills it
+        fn split(&mut self, mut node: u32) {
+            loop {
+                let sibling = self.nodes.len() as u32;
+
+                let (new_node, moved) = match &mut self.nodes[node as usize] {
+                    Node::Leaf(values) => {
+                        let back = values.split_off(values.len() / 2);
+                        (Node::Leaf(back.clone()), back)
+                    }
+                    Node::Branch { children, counts } => {
+                        let mid = children.len() / 2;
+                        let back = children.split_off(mid);
+                        let back_counts = counts.split_off(mid);
+                        (
+                            Node::Branch {
+                                children: back.clone(),
+                                counts: back_counts,
+                            },
+                            back,
+                        )
+                    }
+                };
+
+                let is_leaf = matches!(new_node, Node::Leaf(_));
+                self.nodes.push(new_node);
+                self.parents.push(NONE);
+
+                for moved in moved {
+                    if is_leaf {
+                        self.leaf_of[moved as usize] = sibling;
+                    } else {
+                        self.parents[moved as usize] = sibling;
+                    }
+                }
 
-impl CircularBuffer {
-    fn new(capacity: usize) -> CircularBuffer {
-        let mut data = Vec::with_capacity(capacity);
-        data.push(0);
-        CircularBuffer { data, pos: 0 }
+                let (left_count, right_count) = (self.count(node), self.count(sibling));
+                let parent = self.parents[node as usize];
+
+                if parent == NONE {
+                    let root = self.nodes.len() as u32;
+                    self.nodes.push(Node::Branch {
+                        children: vec![node, sibling],
+                        counts: vec![left_count, right_count],
+                    });
+                    self.parents.push(NONE);
+                    self.parents[node as usize] = root;
+                    self.parents[sibling as usize] = root;
+                    self.root = root;
+                    return;
+                }
+
+                self.parents[sibling as usize] = parent;
+                let full = match &mut self.nodes[parent as usize] {
+                    Node::Branch { children, counts } => {
+                        let slot = children.iter().position(|&c| c == node).unwrap();
+                        counts[slot] = left_count;
+                        children.insert(slot + 1, sibling);
+                        counts.insert(slot + 1, right_count);
+                        children.len() > BRANCH_MAX
+                    }
+                    Node::Leaf(_) => unreachable!(),
+                };
+
+                if !full {
+                    return;
+                }
+                node = parent;
+            }
+        }
     }
 
-    fn advance(&mut self, amt: usize) {
-        for _ in 0..amt {
-            self.pos = self.data[self.pos];
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_matches_vec() {
+            let mut rope = Rope::new();
+            let mut expected: Vec<u32> = Vec::new();
+
+            // enough to split leaves and branches several levels up
+            let mut state: u64 = 12345;
+            for value in 0..100_000_u32 {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let index = (state >> 33) as usize % (expected.len() + 1);
+                rope.insert(index, value);
+                expected.insert(index, value);
+            }
+
+            assert_eq!(rope.len(), expected.len());
+            for (i, &value) in expected.iter().enumerate().step_by(97) {
+                assert_eq!(rope.get(i), Some(value));
+                assert_eq!(rope.position(value), Some(i));
+            }
+            assert_eq!(rope.get(expected.len()), None);
+            assert_eq!(rope.position(100_000), None);
         }
     }
+}
 
-    fn extend(&mut self) {
-        // this will be the next index
-        let next = self.data.len();
+/// The spinlock's circular buffer, which can be queried anywhere after any number of
+/// insertions, not just next to the last one
+pub struct Spinlock {
+    step: usize,
+    buffer: rope::Rope,
+    /// Index of the most recently inserted value
+    cursor: usize,
+}
+
+impl Spinlock {
+    pub fn new(step: usize) -> Spinlock {
+        let mut buffer = rope::Rope::new();
+        buffer.insert(0, 0);
+        Spinlock {
+            step,
+            buffer,
+            cursor: 0,
+        }
+    }
 
-        // this will be the successor of next (which will be the successor of pos)
-        // that is, next will be inserted between pos and succ(pos)
-        let next_next = self.data[self.pos];
+    /// Steps forward and inserts the next value, `n` times over
+    pub fn run(&mut self, n: usize) {
+        for _ in 0..n {
+            let len = self.buffer.len();
+            self.cursor = (self.cursor + self.step) % len + 1;
+            self.buffer.insert(self.cursor, len as u32);
+        }
+    }
 
-        // self.data[next] = next_next
-        self.data.push(next_next);
+    /// How many values have been inserted, not counting the starting 0
+    pub fn insertions(&self) -> usize {
+        self.buffer.len() - 1
+    }
 
-        self.data[self.pos] = next;
+    /// The value `offset` places round from the one just inserted; negative goes backwards
+    pub fn at_offset(&self, offset: i64) -> usize {
+        let len = self.buffer.len() as i64;
+        let index = (self.cursor as i64 + offset).rem_euclid(len);
+        self.buffer.get(index as usize).unwrap() as usize
+    }
 
-        // finally, set pos to the new index
-        self.pos = next;
+    /// The value straight after `value`, if `value` has been inserted yet
+    pub fn value_after(&self, value: usize) -> Option<usize> {
+        if value > u32::MAX as usize {
+            return None;
+        }
+        let position = self.buffer.position(value as u32)?;
+        let next = (position + 1) % self.buffer.len();
+        Some(self.buffer.get(next).unwrap() as usize)
     }
 }
 
 fn run_17a_with_input(input: usize) -> usize {
-    let mut buffer = CircularBuffer::new(2018);
-    for _ in 0..2017 {
-        buffer.advance(input);
-        buffer.extend();
-    }
+    let mut spinlock = Spinlock::new(input);
+    spinlock.run(2017);
 
-    buffer.data[2017]
+    spinlock.at_offset(1)
 }
 
 pub fn run_17a() -> usize {
@@ -52,45 +306,55 @@ pub fn run_17a() -> usize {
 }
 
 fn run_17b_with_input(skip_size: usize, iterations: usize) -> usize {
-    let mut buffer = CircularBuffer::new(iterations + 1);
-
-    let start = std::time::Instant::now();
-    let mut batch_start = std::time::Instant::now();
-
-    let mut adv_time = 0.0;
-    let mut ext_time = 0.0;
-
-    for _ in 0..iterations {
-        let now = start.elapsed().as_secs_f32();
-        buffer.advance(skip_size);
-        let adv = start.elapsed().as_secs_f32();
-        buffer.extend();
-        let ext = start.elapsed().as_secs_f32();
-
-        adv_time = adv_time * 0.9 + (adv - now) * 0.1;
-        ext_time = ext_time * 0.9 * (ext - adv) * 0.1;
-
-        if buffer.data.len() % (1 << 17) == 0 {
-            println!(
-                "Iteration: {}; {:.3} sec",
-                buffer.data.len(),
-                start.elapsed().as_secs_f32()
-            );
-            println!("Batch time: {:.3} sec", batch_start.elapsed().as_secs_f32());
-            batch_start = std::time::Instant::now();
-            println!("Adv time {:.10} sec", adv_time);
-            println!("Ext time {:.10} sec", ext_time);
-            println!();
+    // 0 never moves from the front, so only insertions right after it matter, and for
+    // those only the insertion point needs tracking
+    let mut cursor = 0;
+    let mut after_zero = 0;
+
+    for value in 1..=iterations {
+        cursor = (cursor + skip_size) % value + 1;
+        if cursor == 1 {
+            after_zero = value;
         }
     }
 
-    buffer.data[0]
+    after_zero
 }
 
 pub fn run_17b() -> usize {
     run_17b_with_input(INPUT, 50_000_000)
 }
 
+/// Runs a spinlock and answers queries about the buffer afterwards: "after:X" for the value
+/// following X, or "offset:K" for the value K places on from the last insertion
+pub fn query_17(step: usize, insertions: usize, queries: &[String]) -> String {
+    let mut spinlock = Spinlock::new(step);
+    spinlock.run(insertions);
+
+    let mut out = vec![format!(
+        "{} insertions with step {}",
+        spinlock.insertions(),
+        step
+    )];
+
+    for query in queries {
+        let mut parts = query.splitn(2, ':');
+        let answer = match (parts.next(), parts.next().map(str::parse::<i64>)) {
+            (Some("after"), Some(Ok(value))) if value >= 0 => {
+                match spinlock.value_after(value as usize) {
+                    Some(next) => next.to_string(),
+                    None => "not inserted yet".to_string(),
+                }
+            }
+            (Some("offset"), Some(Ok(offset))) => spinlock.at_offset(offset).to_string(),
+            _ => "unrecognized query; use after:X or offset:K".to_string(),
+        };
+        out.push(format!("{}: {}", query, answer));
+    }
+
+    out.join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -112,4 +376,37 @@ mod tests {
         assert_eq!(run_17b_with_input(3, 8), 5);
         assert_eq!(run_17b_with_input(3, 9), 9);
     }
+
+    #[test]
+    fn spinlock_queries() {
+        // 0 (9) 5  7  2  4  3  8  6  1
+        let mut spinlock = Spinlock::new(3);
+        spinlock.run(9);
+
+        assert_eq!(spinlock.at_offset(0), 9);
+        assert_eq!(spinlock.at_offset(1), 5);
+        assert_eq!(spinlock.at_offset(-1), 0);
+        assert_eq!(spinlock.at_offset(-2), 1);
+        assert_eq!(spinlock.at_offset(10), 9);
+        assert_eq!(spinlock.value_after(1), Some(0));
+        assert_eq!(spinlock.value_after(2), Some(4));
+        assert_eq!(spinlock.value_after(10), None);
+
+        // the general queries agree with the part B shortcut
+        let mut spinlock = Spinlock::new(329);
+        for n in [1, 10, 1000, 20_000].iter().copied() {
+            spinlock.run(n - spinlock.insertions());
+            assert_eq!(spinlock.value_after(0), Some(run_17b_with_input(329, n)));
+        }
+
+        assert_eq!(
+            query_17(3, 9, &["after:2".to_string(), "offset:-1".to_string()]),
+            "9 insertions with step 3\nafter:2: 4\noffset:-1: 0"
+        );
+        assert_eq!(
+            query_17(3, 9, &["after:12".to_string(), "before:1".to_string()]),
+            "9 insertions with step 3\nafter:12: not inserted yet\n\
+             before:1: unrecognized query; use after:X or offset:K"
+        );
+    }
 }
@@ -9,18 +9,6 @@ enum Square {
     Letter(char),
 }
 
-impl Square {
-    fn is_unidirectional(self) -> bool {
-        match self {
-            Square::Empty => false,
-            Square::Corner => false,
-            Square::PipeVertical => true,
-            Square::PipeHorizontal => true,
-            Square::Letter(_) => true,
-        }
-    }
-}
-
 struct Grid {
     data: Vec<Vec<Square>>,
     width: usize,
@@ -42,135 +30,468 @@ impl Grid {
     }
 }
 
-fn parse(input: &str) -> Grid {
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Dir {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Dir {
+    /// The next square along, or None when that would be off the top or left edge
+    fn step(self, x: usize, y: usize) -> Option<(usize, usize)> {
+        match self {
+            Dir::Up => y.checked_sub(1).map(|y| (x, y)),
+            Dir::Down => Some((x, y + 1)),
+            Dir::Left => x.checked_sub(1).map(|x| (x, y)),
+            Dir::Right => Some((x + 1, y)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Dir::Up => "up",
+            Dir::Down => "down",
+            Dir::Left => "left",
+            Dir::Right => "right",
+        }
+    }
+
+    /// The ways a corner could turn, coming in this way
+    fn turns(self) -> [Dir; 2] {
+        match self {
+            Dir::Up | Dir::Down => [Dir::Left, Dir::Right],
+            Dir::Left | Dir::Right => [Dir::Up, Dir::Down],
+        }
+    }
+}
+
+/// Something wrong with a tube map, with 0-based (column, row) coordinates
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Problem {
+    InvalidChar { x: usize, y: usize, found: char },
+    NoEntry,
+    MultipleEntries(Vec<usize>),
+    AmbiguousCorner { x: usize, y: usize },
+    DeadEnd { x: usize, y: usize },
+    LooseEnd { x: usize, y: usize },
+    Loop { x: usize, y: usize },
+    Unreachable { x: usize, y: usize, squares: usize },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::InvalidChar { x, y, found } => {
+                write!(f, "({}, {}): unexpected character {:?}", x, y, found)
+            }
+            Problem::NoEntry => write!(f, "no entry point on the top row"),
+            Problem::MultipleEntries(xs) => {
+                let xs: Vec<String> = xs.iter().map(|x| format!("({}, 0)", x)).collect();
+                write!(f, "several entry points on the top row: {}", xs.join(", "))
+            }
+            Problem::AmbiguousCorner { x, y } => {
+                write!(f, "({}, {}): corner could turn either way", x, y)
+            }
+            Problem::DeadEnd { x, y } => write!(f, "({}, {}): corner with nowhere to turn", x, y),
+            Problem::LooseEnd { x, y } => {
+                write!(
+                    f,
+                    "({}, {}): the track stops without reaching a letter",
+                    x, y
+                )
+            }
+            Problem::Loop { x, y } => write!(f, "({}, {}): the route loops forever", x, y),
+            Problem::Unreachable { x, y, squares } => write!(
+                f,
+                "({}, {}): segment of {} squares the route never reaches",
+                x, y, squares
+            ),
+        }
+    }
+}
+
+fn parse(input: &str) -> Result<Grid, Vec<Problem>> {
+    let mut problems = Vec::new();
+
     let rows = input
         .lines()
-        .map(|line| {
+        .enumerate()
+        .map(|(y, line)| {
             line.chars()
-                .map(|c| match c {
+                .enumerate()
+                .map(|(x, c)| match c {
                     ' ' => Square::Empty,
                     '|' => Square::PipeVertical,
                     '-' => Square::PipeHorizontal,
                     '+' => Square::Corner,
-                    letter => Square::Letter(letter),
+                    letter if letter.is_ascii_alphabetic() => Square::Letter(letter),
+                    found => {
+                        problems.push(Problem::InvalidChar { x, y, found });
+                        Square::Empty
+                    }
                 })
                 .collect::<Vec<Square>>()
         })
-        .collect();
-    Grid::new(rows)
-}
+        .collect::<Vec<_>>();
 
-fn run_19a_with_input(input: &str) -> String {
-    let grid = parse(input);
+    if rows.is_empty() {
+        problems.push(Problem::NoEntry);
+    }
 
-    let mut out = String::new();
+    if problems.is_empty() {
+        Ok(Grid::new(rows))
+    } else {
+        Err(problems)
+    }
+}
 
-    let start_x = (0..grid.width)
-        .filter(|x| grid.get(*x, 0) == Square::PipeVertical)
-        .next()
-        .expect("Top row should have a vertical pipe");
+/// Every square the packet passes through, in order, with the way it heads on from there,
+/// and the letters it collects
+struct Route {
+    steps: Vec<(usize, usize, Dir)>,
+    letters: String,
+}
+
+/// Follows the line down from the entry at `(start_x, 0)` until it runs out. Every square
+/// can be passed at most twice, once each way where lines cross, so a longer route
+/// than that must be going round in circles.
+fn walk(grid: &Grid, start_x: usize) -> Result<Route, Problem> {
+    let filled = grid
+        .data
+        .iter()
+        .flat_map(|row| row.iter())
+        .filter(|&&square| square != Square::Empty)
+        .count();
 
-    let mut pos = (start_x, 1);
-    let mut dx = 0;
-    let mut dy = 1;
+    let mut route = Route {
+        steps: Vec::new(),
+        letters: String::new(),
+    };
+    let (mut x, mut y, mut dir) = (start_x, 0, Dir::Down);
+    let open = |(x, y): (usize, usize)| grid.get(x, y) != Square::Empty;
 
     loop {
-        let (x, y) = pos;
         match grid.get(x, y) {
-            Square::Empty => {
-                // This means we "got to the end" of the path and we're done
-                return out;
-            }
             Square::Corner => {
-                if dy != 0 {
-                    // then we're currently vertical, we need horizontal
-                    // code assumes there is exactly one possible solution, does not validate
-                    dy = 0;
-                    if x > 0 && grid.get(x - 1, y).is_unidirectional() {
-                        dx = -1;
-                    } else {
-                        dx = 1;
-                    }
-                } else {
-                    dx = 0;
-                    if y > 0 && grid.get(x, y - 1).is_unidirectional() {
-                        dy = -1;
-                    } else {
-                        dy = 1;
-                    }
-                }
-            }
-            Square::PipeVertical | Square::PipeHorizontal => {
-                // keep going
+                let exits: Vec<Dir> = dir
+                    .turns()
+                    .iter()
+                    .copied()
+                    .filter(|turn| turn.step(x, y).is_some_and(open))
+                    .collect();
+
+                dir = match exits.as_slice() {
+                    [] => return Err(Problem::DeadEnd { x, y }),
+                    [exit] => *exit,
+                    _ => return Err(Problem::AmbiguousCorner { x, y }),
+                };
             }
-            Square::Letter(letter) => {
-                out.push(letter);
+            Square::Letter(letter) => route.letters.push(letter),
+            Square::Empty | Square::PipeVertical | Square::PipeHorizontal => {}
+        }
+
+        route.steps.push((x, y, dir));
+        if route.steps.len() > 2 * filled {
+            return Err(Problem::Loop { x, y });
+        }
+
+        match dir.step(x, y) {
+            Some(next) if open(next) => {
+                x = next.0;
+                y = next.1;
             }
+            // the end of the line, which has to be a letter rather than a pipe to nowhere
+            _ => match grid.get(x, y) {
+                Square::Letter(_) => return Ok(route),
+                _ => return Err(Problem::LooseEnd { x, y }),
+            },
         }
-        pos.0 = (pos.0 as i32 + dx) as usize;
-        pos.1 = (pos.1 as i32 + dy) as usize;
     }
 }
 
-pub fn run_19a() -> String {
-    run_19a_with_input(INPUT)
+/// A tube map that has been checked to have exactly one route through it, which covers
+/// every piece of track
+struct TubeMap {
+    grid: Grid,
+    route: Route,
 }
 
-fn run_19b_with_input(input: &str) -> usize {
-    let grid = parse(input);
+impl TubeMap {
+    fn parse(input: &str) -> Result<TubeMap, Vec<Problem>> {
+        let grid = parse(input)?;
 
-    let start_x = (0..grid.width)
-        .filter(|x| grid.get(*x, 0) == Square::PipeVertical)
-        .next()
-        .expect("Top row should have a vertical pipe");
+        let entries: Vec<usize> = (0..grid.width)
+            .filter(|&x| grid.get(x, 0) != Square::Empty)
+            .collect();
+        let start_x = match entries.as_slice() {
+            [] => return Err(vec![Problem::NoEntry]),
+            [x] => *x,
+            _ => return Err(vec![Problem::MultipleEntries(entries)]),
+        };
 
-    let mut pos = (start_x, 1);
-    let mut dx = 0;
-    let mut dy = 1;
+        let route = walk(&grid, start_x).map_err(|problem| vec![problem])?;
 
-    let mut steps = 1;
+        let problems = TubeMap::unreachable(&grid, &route);
+        if problems.is_empty() {
+            Ok(TubeMap { grid, route })
+        } else {
+            Err(problems)
+        }
+    }
 
-    loop {
-        let (x, y) = pos;
-        match grid.get(x, y) {
-            Square::Empty => {
-                // This means we "got to the end" of the path and we're done
-                return steps;
-            }
-            Square::Corner => {
-                if dy != 0 {
-                    // then we're currently vertical, we need horizontal
-                    // code assumes there is exactly one possible solution, does not validate
-                    dy = 0;
-                    if x > 0 && grid.get(x - 1, y).is_unidirectional() {
-                        dx = -1;
-                    } else {
-                        dx = 1;
+    /// Every connected bit of track the route doesn't pass through, by its first square
+    fn unreachable(grid: &Grid, route: &Route) -> Vec<Problem> {
+        let height = grid.data.len();
+        let mut seen = vec![vec![false; grid.width]; height];
+        for &(x, y, _) in route.steps.iter() {
+            seen[y][x] = true;
+        }
+
+        let mut problems = Vec::new();
+        for y in 0..height {
+            for x in 0..grid.width {
+                if seen[y][x] || grid.get(x, y) == Square::Empty {
+                    continue;
+                }
+
+                let mut squares = 0;
+                let mut stack = vec![(x, y)];
+                seen[y][x] = true;
+
+                while let Some((cx, cy)) = stack.pop() {
+                    squares += 1;
+                    for dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right].iter() {
+                        if let Some((nx, ny)) = dir.step(cx, cy) {
+                            if ny < height
+                                && nx < grid.width
+                                && !seen[ny][nx]
+                                && grid.get(nx, ny) != Square::Empty
+                            {
+                                seen[ny][nx] = true;
+                                stack.push((nx, ny));
+                            }
+                        }
                     }
+                }
+
+                problems.push(Problem::Unreachable { x, y, squares });
+            }
+        }
+
+        problems
+    }
+}
+
+mod render {
+    use super::{Dir, Square, TubeMap};
+
+    /// The squares where the route starts, turns or ends: (step, x, y, heading from there)
+    pub(super) fn waypoints(map: &TubeMap) -> Vec<(usize, usize, usize, Dir)> {
+        let steps = &map.route.steps;
+
+        steps
+            .iter()
+            .enumerate()
+            .filter(|&(i, step)| i == 0 || i == steps.len() - 1 || step.2 != steps[i - 1].2)
+            .map(|(i, &(x, y, dir))| (i, x, y, dir))
+            .collect()
+    }
+
+    /// Each letter picked up, with the step it was picked up on
+    fn letters(map: &TubeMap) -> Vec<(usize, char)> {
+        map.route
+            .steps
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &(x, y, _))| match map.grid.get(x, y) {
+                Square::Letter(letter) => Some((i, letter)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn visited(map: &TubeMap) -> Vec<Vec<bool>> {
+        let mut visited = vec![vec![false; map.grid.width]; map.grid.data.len()];
+        for &(x, y, _) in map.route.steps.iter() {
+            visited[y][x] = true;
+        }
+        visited
+    }
+
+    /// The map with the route in green, its letters in bold yellow, and the turns and
+    /// letters listed underneath with the step they come on
+    pub(super) fn to_ansi(map: &TubeMap) -> String {
+        const GREEN: &str = "\u{1b}[32m";
+        const YELLOW: &str = "\u{1b}[1;33m";
+        const DIM: &str = "\u{1b}[2m";
+        const RESET: &str = "\u{1b}[0m";
+
+        let visited = visited(map);
+        let mut out = String::new();
+
+        for (y, row) in map.grid.data.iter().enumerate() {
+            for (x, square) in row.iter().enumerate() {
+                let (c, color) = match square {
+                    Square::Empty => (' ', ""),
+                    Square::Corner => ('+', GREEN),
+                    Square::PipeVertical => ('|', GREEN),
+                    Square::PipeHorizontal => ('-', GREEN),
+                    Square::Letter(letter) => (*letter, YELLOW),
+                };
+
+                if color.is_empty() {
+                    out.push(c);
+                } else if visited[y][x] {
+                    out.push_str(&format!("{}{}{}", color, c, RESET));
                 } else {
-                    dx = 0;
-                    if y > 0 && grid.get(x, y - 1).is_unidirectional() {
-                        dy = -1;
-                    } else {
-                        dy = 1;
-                    }
+                    out.push_str(&format!("{}{}{}", DIM, c, RESET));
                 }
             }
-            Square::PipeVertical | Square::PipeHorizontal | Square::Letter(_) => {
-                // keep going
+            out.push('\n');
+        }
+
+        out.push('\n');
+        for (step, x, y, dir) in waypoints(map) {
+            out.push_str(&format!(
+                "step {:>5}: ({}, {}) heading {}\n",
+                step,
+                x,
+                y,
+                dir.name()
+            ));
+        }
+
+        let letters: Vec<String> = letters(map)
+            .iter()
+            .map(|(step, letter)| format!("{}{}{} @{}", YELLOW, letter, RESET, step))
+            .collect();
+        out.push_str(&format!("Letters: {}", letters.join(", ")));
+
+        out
+    }
+
+    /// Track as grey squares, the route over it as a green line with its squares
+    /// highlighted, letters labelled, and the step number written by every turn
+    pub(super) fn to_svg(map: &TubeMap) -> String {
+        const CELL: usize = 10;
+        let center = |x: usize, y: usize| (x * CELL + CELL / 2, y * CELL + CELL / 2);
+
+        let visited = visited(map);
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\" font-family=\"monospace\">\n",
+            map.grid.width * CELL,
+            map.grid.data.len() * CELL
+        );
+
+        for (y, row) in map.grid.data.iter().enumerate() {
+            for (x, square) in row.iter().enumerate() {
+                if *square == Square::Empty {
+                    continue;
+                }
+                let fill = if visited[y][x] { "#bfe6c4" } else { "#dddddd" };
+                out.push_str(&format!(
+                    "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                    x * CELL,
+                    y * CELL,
+                    CELL,
+                    CELL,
+                    fill
+                ));
             }
         }
-        pos.0 = (pos.0 as i32 + dx) as usize;
-        pos.1 = (pos.1 as i32 + dy) as usize;
 
-        steps += 1;
+        let points: Vec<String> = waypoints(map)
+            .iter()
+            .map(|&(_, x, y, _)| {
+                let (cx, cy) = center(x, y);
+                format!("{},{}", cx, cy)
+            })
+            .collect();
+        out.push_str(&format!(
+            "  <polyline points=\"{}\" fill=\"none\" stroke=\"#2e9e44\" stroke-width=\"2\"/>\n",
+            points.join(" ")
+        ));
+
+        for (step, letter) in letters(map) {
+            let (x, y, _) = map.route.steps[step];
+            let (cx, cy) = center(x, y);
+            out.push_str(&format!(
+                "  <text x=\"{}\" y=\"{}\" font-size=\"{}\" font-weight=\"bold\" fill=\"#b8860b\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>\n",
+                cx, cy, CELL, letter
+            ));
+        }
+
+        for (step, x, y, _) in waypoints(map) {
+            out.push_str(&format!(
+                "  <text x=\"{}\" y=\"{}\" font-size=\"{}\" fill=\"#555555\">{}</text>\n",
+                x * CELL + CELL,
+                y * CELL,
+                CELL / 2,
+                step
+            ));
+        }
+
+        out.push_str("</svg>");
+        out
     }
 }
 
+fn run_19a_with_input(input: &str) -> String {
+    TubeMap::parse(input).unwrap().route.letters
+}
+
+pub fn run_19a() -> String {
+    run_19a_with_input(INPUT)
+}
+
+fn run_19b_with_input(input: &str) -> usize {
+    TubeMap::parse(input).unwrap().route.steps.len()
+}
+
 pub fn run_19b() -> usize {
     run_19b_with_input(INPUT)
 }
 
+/// Checks a tube map and lists whatever is wrong with it, or sums up the route if nothing is
+pub fn check_19(input: &str) -> String {
+    match TubeMap::parse(input) {
+        Ok(map) => format!(
+            "OK: {} steps through a {}x{} map, collecting {}",
+            map.route.steps.len(),
+            map.grid.width,
+            map.grid.data.len(),
+            map.route.letters
+        ),
+        Err(problems) => problems
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Draws the route over the map as "ansi" or "svg", or lists its "waypoints" as
+/// (x, y, direction) for where it starts, every turn and where it ends
+pub fn route_19(input: &str, format: &str) -> String {
+    let map = match TubeMap::parse(input) {
+        Ok(map) => map,
+        Err(_) => return check_19(input),
+    };
+
+    match format {
+        "ansi" => render::to_ansi(&map),
+        "svg" => render::to_svg(&map),
+        "waypoints" => render::waypoints(&map)
+            .iter()
+            .map(|&(_, x, y, dir)| format!("({}, {}, {})", x, y, dir.name()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        other => format!("Unrecognized route format {}", other),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -192,4 +513,84 @@ mod tests {
     fn sample_19b() {
         assert_eq!(run_19b_with_input(SAMPLE), 38);
     }
+
+    #[test]
+    fn sample_19_check() {
+        assert_eq!(
+            check_19(SAMPLE),
+            "OK: 38 steps through a 15x6 map, collecting ABCDEF"
+        );
+
+        // a spur off the corner at the bottom makes it ambiguous
+        let ambiguous = SAMPLE.replace("     +B-+  +--+", "    -+B-+  +--+");
+        assert_eq!(check_19(&ambiguous), "(5, 5): corner could turn either way");
+
+        let dead_end = SAMPLE.replace("     +B-+  +--+", "     +   \n");
+        assert_eq!(check_19(&dead_end), "(5, 5): corner with nowhere to turn");
+
+        // the route has to finish on a letter, not stop partway along a pipe
+        let loose_end = SAMPLE.replace("F---|----E|--+", "    |----E|--+");
+        assert_eq!(
+            check_19(&loose_end),
+            "(5, 3): the track stops without reaching a letter"
+        );
+
+        let two_entries = SAMPLE.replacen("     |", "     |   |", 1);
+        assert_eq!(
+            check_19(&two_entries),
+            "several entry points on the top row: (5, 0), (9, 0)"
+        );
+        assert_eq!(check_19(""), "no entry point on the top row");
+
+        let stray = format!("{}\n   --X\n  #\n", SAMPLE);
+        assert_eq!(check_19(&stray), "(2, 8): unexpected character '#'");
+
+        let stray = format!("{}\n   --X\n", SAMPLE);
+        assert_eq!(
+            check_19(&stray),
+            "(3, 7): segment of 3 squares the route never reaches"
+        );
+
+        let looped = " |\n +-+\n +-+";
+        assert_eq!(check_19(looped), "(2, 1): the route loops forever");
+        let forked = "  |\n+-+-+\n| | |\n+-+-+";
+        assert_eq!(check_19(forked), "(2, 1): corner could turn either way");
+    }
+
+    #[test]
+    fn sample_19_route() {
+        assert_eq!(
+            route_19(SAMPLE, "waypoints"),
+            "(5, 0, down)
+(5, 5, right)
+(8, 5, up)
+(8, 1, right)
+(11, 1, down)
+(11, 5, right)
+(14, 5, up)
+(14, 3, left)
+(1, 3, left)"
+        );
+
+        let ansi = route_19(SAMPLE, "ansi");
+        assert!(ansi.contains("step     5: (5, 5) heading right"));
+        assert!(ansi.contains("step    37: (1, 3) heading left"));
+        assert!(ansi.ends_with(
+            "Letters: \u{1b}[1;33mA\u{1b}[0m @2, \u{1b}[1;33mB\u{1b}[0m @6, \
+             \u{1b}[1;33mC\u{1b}[0m @16, \u{1b}[1;33mD\u{1b}[0m @23, \
+             \u{1b}[1;33mE\u{1b}[0m @28, \u{1b}[1;33mF\u{1b}[0m @37"
+        ));
+
+        let svg = route_19(SAMPLE, "svg");
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>"));
+        assert_eq!(svg.matches(">A</text>").count(), 1);
+        // 38 steps, three of them back over a square already crossed
+        assert_eq!(svg.matches("#bfe6c4").count(), 35);
+
+        // there's no route to draw on an invalid map, so it just gets its problems reported
+        let stray = format!("{}\n   --X\n", SAMPLE);
+        assert_eq!(route_19(&stray, "ansi"), check_19(&stray));
+        assert_eq!(route_19(SAMPLE, "png"), "Unrecognized route format png");
+    }
 }
@@ -0,0 +1,396 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// The six ways out of a hex on a flat-topped grid, listed clockwise from north
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Dir {
+    N,
+    NE,
+    SE,
+    S,
+    SW,
+    NW,
+}
+
+impl Dir {
+    pub const ALL: [Dir; 6] = [Dir::N, Dir::NE, Dir::SE, Dir::S, Dir::SW, Dir::NW];
+
+    /// The cube coordinates of the neighbor in this direction from the origin
+    pub fn offset(self) -> Cube {
+        match self {
+            Dir::N => Cube { q: 0, r: -1, s: 1 },
+            Dir::NE => Cube { q: 1, r: -1, s: 0 },
+            Dir::SE => Cube { q: 1, r: 0, s: -1 },
+            Dir::S => Cube { q: 0, r: 1, s: -1 },
+            Dir::SW => Cube { q: -1, r: 1, s: 0 },
+            Dir::NW => Cube { q: -1, r: 0, s: 1 },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dir::N => "n",
+            Dir::NE => "ne",
+            Dir::SE => "se",
+            Dir::S => "s",
+            Dir::SW => "sw",
+            Dir::NW => "nw",
+        }
+    }
+}
+
+impl std::str::FromStr for Dir {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Dir, String> {
+        Dir::ALL
+            .iter()
+            .copied()
+            .find(|dir| dir.name() == s)
+            .ok_or_else(|| format!("Unrecognized direction {}", s))
+    }
+}
+
+/// Cube coordinates: three axes at 120 degrees to each other, with q + r + s always 0.
+/// q grows to the east, r to the south-west and s to the north-west, so every `Dir` moves
+/// one step along exactly two of them.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Cube {
+    pub q: i64,
+    pub r: i64,
+    pub s: i64,
+}
+
+/// Axial coordinates: cube coordinates with the redundant `s` dropped
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Axial {
+    pub q: i64,
+    pub r: i64,
+}
+
+impl From<Axial> for Cube {
+    fn from(a: Axial) -> Cube {
+        Cube {
+            q: a.q,
+            r: a.r,
+            s: -a.q - a.r,
+        }
+    }
+}
+
+impl From<Cube> for Axial {
+    fn from(c: Cube) -> Axial {
+        Axial { q: c.q, r: c.r }
+    }
+}
+
+impl std::str::FromStr for Axial {
+    type Err = String;
+
+    /// Parses "q,r"
+    fn from_str(s: &str) -> Result<Axial, String> {
+        let bad = || format!("Expected a cell as q,r, not {}", s);
+        let mut parts = s.split(',').map(|part| part.trim().parse::<i64>());
+
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(q)), Some(Ok(r)), None) => Ok(Axial { q, r }),
+            _ => Err(bad()),
+        }
+    }
+}
+
+impl std::fmt::Display for Axial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.q, self.r)
+    }
+}
+
+impl Add for Cube {
+    type Output = Cube;
+
+    fn add(self, other: Cube) -> Cube {
+        Cube {
+            q: self.q + other.q,
+            r: self.r + other.r,
+            s: self.s + other.s,
+        }
+    }
+}
+
+impl Sub for Cube {
+    type Output = Cube;
+
+    fn sub(self, other: Cube) -> Cube {
+        self + -other
+    }
+}
+
+impl Neg for Cube {
+    type Output = Cube;
+
+    fn neg(self) -> Cube {
+        Cube {
+            q: -self.q,
+            r: -self.r,
+            s: -self.s,
+        }
+    }
+}
+
+impl Mul<i64> for Cube {
+    type Output = Cube;
+
+    fn mul(self, k: i64) -> Cube {
+        Cube {
+            q: self.q * k,
+            r: self.r * k,
+            s: self.s * k,
+        }
+    }
+}
+
+/// Which axis a reflection leaves alone
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Axis {
+    Q,
+    R,
+    S,
+}
+
+impl std::str::FromStr for Axis {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Axis, String> {
+        match s {
+            "q" => Ok(Axis::Q),
+            "r" => Ok(Axis::R),
+            "s" => Ok(Axis::S),
+            other => Err(format!("Unrecognized axis {}", other)),
+        }
+    }
+}
+
+impl Cube {
+    pub const ORIGIN: Cube = Cube { q: 0, r: 0, s: 0 };
+
+    pub fn neighbor(self, dir: Dir) -> Cube {
+        self + dir.offset()
+    }
+
+    pub fn neighbors(self) -> [Cube; 6] {
+        let mut out = [self; 6];
+        for (cell, dir) in out.iter_mut().zip(Dir::ALL.iter()) {
+            *cell = self.neighbor(*dir);
+        }
+        out
+    }
+
+    /// Fewest steps from the origin. Every step changes two coordinates by one each, in
+    /// opposite directions, so this is just the largest coordinate.
+    pub fn length(self) -> i64 {
+        self.q.abs().max(self.r.abs()).max(self.s.abs())
+    }
+
+    pub fn distance(self, other: Cube) -> i64 {
+        (self - other).length()
+    }
+
+    /// Every cell exactly `radius` steps from `self`, clockwise from the south-west corner
+    pub fn ring(self, radius: i64) -> Vec<Cube> {
+        if radius == 0 {
+            return vec![self];
+        }
+
+        let mut out = Vec::with_capacity(6 * radius as usize);
+        let mut cell = self + Dir::SW.offset() * radius;
+
+        for dir in Dir::ALL.iter().copied() {
+            for _ in 0..radius {
+                out.push(cell);
+                cell = cell.neighbor(dir);
+            }
+        }
+
+        out
+    }
+
+    /// Rounds fractional cube coordinates to the nearest cell, fixing up whichever
+    /// coordinate moved the most so they still sum to 0
+    fn round(q: f64, r: f64, s: f64) -> Cube {
+        let (mut rq, mut rr, mut rs) = (q.round(), r.round(), s.round());
+        let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+
+        if dq > dr && dq > ds {
+            rq = -rr - rs;
+        } else if dr > ds {
+            rr = -rq - rs;
+        } else {
+            rs = -rq - rr;
+        }
+
+        Cube {
+            q: rq as i64,
+            r: rr as i64,
+            s: rs as i64,
+        }
+    }
+
+    /// The cells a straight line from `self` to `other` passes through, both ends
+    /// included; each is one step from the last
+    pub fn line(self, other: Cube) -> Vec<Cube> {
+        let n = self.distance(other);
+        if n == 0 {
+            return vec![self];
+        }
+
+        // nudged off the exact midpoint between cells so ties always break the same way
+        let lerp = |a: i64, b: i64, nudge: f64, t: f64| a as f64 + nudge + (b - a) as f64 * t;
+
+        (0..=n)
+            .map(|i| {
+                let t = i as f64 / n as f64;
+                Cube::round(
+                    lerp(self.q, other.q, 1e-6, t),
+                    lerp(self.r, other.r, 2e-6, t),
+                    lerp(self.s, other.s, -3e-6, t),
+                )
+            })
+            .collect()
+    }
+
+    /// A sixth of a turn clockwise about the origin; negative steps turn anticlockwise
+    pub fn rotate(self, steps: i64) -> Cube {
+        let mut out = self;
+        for _ in 0..steps.rem_euclid(6) {
+            out = Cube {
+                q: -out.r,
+                r: -out.s,
+                s: -out.q,
+            };
+        }
+        out
+    }
+
+    pub fn rotate_about(self, center: Cube, steps: i64) -> Cube {
+        (self - center).rotate(steps) + center
+    }
+
+    /// Mirrors across the line through the origin along which `axis` stays fixed
+    pub fn reflect(self, axis: Axis) -> Cube {
+        let Cube { q, r, s } = self;
+        match axis {
+            Axis::Q => Cube { q, r: s, s: r },
+            Axis::R => Cube { q: s, r, s: q },
+            Axis::S => Cube { q: r, r: q, s },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(q: i64, r: i64) -> Cube {
+        Axial { q, r }.into()
+    }
+
+    #[test]
+    fn directions() {
+        for dir in Dir::ALL.iter().copied() {
+            let step = dir.offset();
+            assert_eq!(step.q + step.r + step.s, 0);
+            assert_eq!(step.length(), 1);
+            assert_eq!(dir.name().parse::<Dir>(), Ok(dir));
+        }
+
+        // listed clockwise, so each is a sixth of a turn on from the last
+        for (i, dir) in Dir::ALL.iter().enumerate() {
+            let next = Dir::ALL[(i + 1) % 6];
+            assert_eq!(dir.offset().rotate(1), next.offset());
+            assert_eq!(dir.offset().rotate(3), -dir.offset());
+        }
+
+        // two sides apart add up to the one between
+        assert_eq!(Dir::N.offset() + Dir::SE.offset(), Dir::NE.offset());
+        assert!("e".parse::<Dir>().is_err());
+    }
+
+    #[test]
+    fn rings_and_distances() {
+        let center = cube(3, -5);
+
+        assert_eq!(center.ring(0), vec![center]);
+
+        for radius in 1..6 {
+            let ring = center.ring(radius);
+            assert_eq!(ring.len(), 6 * radius as usize);
+            assert!(ring.iter().all(|c| c.distance(center) == radius));
+
+            // it really does go round, a step at a time
+            for (i, cell) in ring.iter().enumerate() {
+                assert_eq!(cell.distance(ring[(i + 1) % ring.len()]), 1);
+            }
+
+            let mut unique = ring.clone();
+            unique.sort_by_key(|c| (c.q, c.r));
+            unique.dedup();
+            assert_eq!(unique.len(), ring.len());
+        }
+
+        assert_eq!(center.neighbors().len(), 6);
+        assert!(center.neighbors().iter().all(|n| n.distance(center) == 1));
+        assert_eq!(cube(2, -3).length(), 3);
+        assert_eq!(Axial::from(cube(2, -3)), Axial { q: 2, r: -3 });
+        assert_eq!("2, -3".parse::<Axial>(), Ok(Axial { q: 2, r: -3 }));
+        assert_eq!(Axial { q: 2, r: -3 }.to_string(), "2,-3");
+        assert!("2,-3,1".parse::<Axial>().is_err());
+    }
+
+    #[test]
+    fn lines() {
+        let a = cube(-2, 4);
+        let b = cube(5, -1);
+        let line = a.line(b);
+
+        assert_eq!(line.len() as i64, a.distance(b) + 1);
+        assert_eq!(line[0], a);
+        assert_eq!(*line.last().unwrap(), b);
+        for pair in line.windows(2) {
+            assert_eq!(pair[0].distance(pair[1]), 1);
+        }
+
+        // straight along an axis is exact
+        assert_eq!(
+            Cube::ORIGIN.line(Dir::N.offset() * 3),
+            vec![
+                Cube::ORIGIN,
+                Dir::N.offset(),
+                Dir::N.offset() * 2,
+                Dir::N.offset() * 3
+            ]
+        );
+    }
+
+    #[test]
+    fn rotations_and_reflections() {
+        let c = cube(2, -5);
+
+        assert_eq!(c.rotate(6), c);
+        assert_eq!(c.rotate(-1), c.rotate(5));
+        assert_eq!(c.rotate(3), -c);
+        assert_eq!(c.rotate(2).length(), c.length());
+
+        let center = cube(1, 1);
+        assert_eq!(center.rotate_about(center, 2), center);
+        assert_eq!(
+            c.rotate_about(center, 1).distance(center),
+            c.distance(center)
+        );
+
+        for axis in [Axis::Q, Axis::R, Axis::S].iter().copied() {
+            assert_eq!(c.reflect(axis).reflect(axis), c);
+            assert_eq!(c.reflect(axis).length(), c.length());
+        }
+        assert_eq!(Dir::NE.offset().reflect(Axis::Q), Dir::SE.offset());
+        assert_eq!(Dir::N.offset().reflect(Axis::Q), Dir::S.offset());
+    }
+}
@@ -26,14 +26,58 @@ mod day23;
 mod day24;
 mod day25;
 
+mod cycles;
+mod hex;
 mod lib;
 
+fn read_input_file(args: &[String]) -> String {
+    let path = args
+        .get(2)
+        .unwrap_or_else(|| panic!("'{}' needs a path to an input file", args[1]));
+
+    std::fs::read_to_string(path).unwrap_or_else(|e| panic!("Could not read '{}': {}", path, e))
+}
+
+fn parse_arg<T: std::str::FromStr>(args: &[String], index: usize) -> Option<T> {
+    args.get(index).map(|arg| {
+        arg.parse()
+            .unwrap_or_else(|_| panic!("Could not parse argument '{}'", arg))
+    })
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 2 {
+    if args.len() < 2 {
         println!("Usage: [run] [problemNumber]");
         println!("Example: cargo run --release -- 1a");
+        println!();
+        println!("Tools: [run] [tool] [inputFile] [args...]");
+        println!("  1-captcha [inputFile] [next|half|offset] [radix]");
+        println!("  2-checksum [inputFile] [reducer] [csv|tsv|whitespace] [header]");
+        println!("  3-spiral [rule] [size] [text|csv] [seed]");
+        println!("  4-policy [configFile] [inputFile]");
+        println!("  5-maze [inputFile] [rule] [maxSteps]");
+        println!("  5-bench [inputFile] [runs]");
+        println!("  6-cycle [inputFile] [floyd|brent|hashing]");
+        println!("  6-generate [banks] [maxBlocks] [seed]");
+        println!("  7-rebalance [inputFile]");
+        println!("  7-export [inputFile] [dot|json|tree]");
+        println!("  9-stream [inputFile]");
+        println!("  9-transform [inputFile] [stream|pretty|json] [keep|unescape|strip]");
+        println!("  11-path [inputFile] [summary|ascii|svg]");
+        println!("  11-hex [distance|neighbors|ring|line|rotate|reflect] [q,r] [args...]");
+        println!("  12-network [inputFile] [from to]");
+        println!("  13-delays [inputFile] [count]");
+        println!("  13-animate [inputFile] [delay] [castFile]");
+        println!("  15-bench [inputFile] [runs]");
+        println!("  16-dance [inputFile] [times] [cohortSize]");
+        println!("  16-rewrite [inputFile] [simplify|inverse] [cohortSize]");
+        println!("  16-diff [from] [to]");
+        println!("  17-query [step] [insertions] [after:X|offset:K...]");
+        println!("  19-check [inputFile]");
+        println!("  19-route [inputFile] [ansi|svg|waypoints]");
+        return;
     }
 
     let start = std::time::Instant::now();
@@ -114,6 +158,114 @@ fn main() {
         "25a" => day25::run_25a().to_string(),
         "25b" => day25::run_25b().to_string(),
 
+        "1-captcha" => day01::captcha_file_1(
+            args.get(2)
+                .expect("'1-captcha' needs a path to an input file"),
+            args.get(3).map(|offset| offset.as_str()).unwrap_or("next"),
+            parse_arg(&args, 4).unwrap_or(10),
+        ),
+        "2-checksum" => day02::report_2(
+            &read_input_file(&args),
+            args.get(3)
+                .map(|reducer| reducer.as_str())
+                .unwrap_or("range"),
+            args.get(4)
+                .map(|format| format.as_str())
+                .unwrap_or("whitespace"),
+            args.get(5)
+                .map(|header| header == "header")
+                .unwrap_or(false),
+        ),
+        "3-spiral" => day03::render_spiral_3(
+            args.get(2).map(|rule| rule.as_str()).unwrap_or("sum"),
+            parse_arg(&args, 3).unwrap_or(9),
+            args.get(4).map(|format| format.as_str()).unwrap_or("text"),
+            parse_arg(&args, 5),
+        ),
+        "4-policy" => {
+            let input_path = args.get(3).expect("'4-policy' needs a passphrase file");
+            let input = std::fs::read_to_string(input_path)
+                .unwrap_or_else(|e| panic!("Could not read '{}': {}", input_path, e));
+            day04::check_policy_4(&read_input_file(&args), &input)
+        }
+        "5-maze" => day05::run_maze_5(
+            &read_input_file(&args),
+            args.get(3).map(|rule| rule.as_str()).unwrap_or("x + 1"),
+            parse_arg(&args, 4),
+        ),
+        "5-bench" => day05::bench_5b(&read_input_file(&args), parse_arg(&args, 3).unwrap_or(5)),
+        "6-cycle" => day06::cycle_6(
+            &read_input_file(&args),
+            parse_arg(&args, 3).unwrap_or(cycles::Method::Brent),
+        ),
+        "6-generate" => day06::generate_banks_6(
+            parse_arg(&args, 2).unwrap_or(16),
+            parse_arg(&args, 3).unwrap_or(16),
+            parse_arg(&args, 4).unwrap_or(0),
+        ),
+        "7-rebalance" => day07::rebalance_7(&read_input_file(&args)),
+        "7-export" => day07::export_7(
+            &read_input_file(&args),
+            args.get(3).map(|format| format.as_str()).unwrap_or("tree"),
+        ),
+        "9-stream" => day09::stream_9(
+            args.get(2)
+                .expect("'9-stream' needs a path to an input file"),
+        ),
+        "9-transform" => day09::transform_9(
+            &read_input_file(&args),
+            args.get(3)
+                .map(|format| format.as_str())
+                .unwrap_or("stream"),
+            args.get(4).map(|mode| mode.as_str()).unwrap_or("keep"),
+        ),
+        "11-path" => day11::path_11(
+            &read_input_file(&args),
+            args.get(3).map(String::as_str).unwrap_or("summary"),
+        ),
+        "11-hex" => day11::hex_11(
+            args.get(2).map(String::as_str).unwrap_or("distance"),
+            args.get(3..).unwrap_or(&[]),
+        ),
+        "12-network" => {
+            let path_query =
+                parse_arg(&args, 3).and_then(|from| parse_arg(&args, 4).map(|to| (from, to)));
+            day12::analyze_12(&read_input_file(&args), path_query)
+        }
+        "13-animate" => day13::animate_13(
+            &read_input_file(&args),
+            parse_arg(&args, 3).unwrap_or(0),
+            args.get(4).map(|path| path.as_str()),
+        ),
+        "13-delays" => {
+            day13::safe_delays_13(&read_input_file(&args), parse_arg(&args, 3).unwrap_or(1))
+        }
+        "15-bench" => day15::bench_15(&read_input_file(&args), parse_arg(&args, 3).unwrap_or(3)),
+        "16-dance" => day16::dance_16(
+            &read_input_file(&args),
+            parse_arg(&args, 3).unwrap_or(1_000_000_000),
+            parse_arg(&args, 4).unwrap_or(16),
+        ),
+        "16-rewrite" => day16::rewrite_16(
+            &read_input_file(&args),
+            args.get(3).map(String::as_str).unwrap_or("simplify"),
+            parse_arg(&args, 4).unwrap_or(16),
+        ),
+        "16-diff" => match (args.get(2), args.get(3)) {
+            (Some(from), Some(to)) => day16::diff_16(from, to),
+            _ => "'16-diff' needs two line-ups".to_string(),
+        },
+        "17-query" => day17::query_17(
+            parse_arg(&args, 2).unwrap_or(329),
+            parse_arg(&args, 3).unwrap_or(2017),
+            args.get(4..).unwrap_or(&[]),
+        ),
+        "19-check" => day19::check_19(&read_input_file(&args)),
+        "19-route" => day19::route_19(
+            &read_input_file(&args),
+            args.get(3).map(String::as_str).unwrap_or("ansi"),
+        ),
+
         other => {
             format!("Unrecognized problem number {}", other)
         }